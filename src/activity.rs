@@ -0,0 +1,161 @@
+// Progress of the request for a reply, shown in the status slot of the chat: a spinner with the time since the request
+// was sent, then the time to the first token and the rate of the tokens as the reply streams in, frozen into a summary
+// once it is finished. The tokens are estimated from the growth of the reply's text, as the chat counts them.
+
+use std::time::{Duration, Instant};
+
+use libmoon::chat::ChatUpdate;
+use ratatui::{style::Color, text::Line};
+
+const SPINNER: [char; 10] = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+
+/// Time between two frames of the spinner
+pub const SPINNER_PERIOD: Duration = Duration::from_millis(100);
+
+#[derive(Default)]
+pub enum Activity {
+    #[default]
+    Idle,
+    Waiting {
+        sent: Instant,
+    },
+    Streaming {
+        sent: Instant,
+        first_token: Instant,
+        tokens: usize,
+    },
+    Done {
+        total: Duration,       // From the request to the end of the reply
+        first_token: Duration, // From the request to the first token
+        tokens: usize,
+        rate: Option<f64>, // Tokens per second once the reply started
+    },
+}
+
+impl Activity {
+    pub fn update(&mut self, update: &ChatUpdate) {
+        let now = Instant::now();
+        *self = match (std::mem::take(self), update) {
+            (_, ChatUpdate::RequestSent) => Activity::Waiting { sent: now },
+            (Activity::Waiting { sent }, ChatUpdate::StreamUpdate) => Activity::Streaming {
+                sent,
+                first_token: now,
+                tokens: 0,
+            },
+            (
+                Activity::Streaming {
+                    sent,
+                    first_token,
+                    tokens,
+                },
+                ChatUpdate::StreamFinished,
+            ) => Activity::Done {
+                total: now - sent,
+                first_token: first_token - sent,
+                tokens,
+                rate: rate(tokens, now - first_token),
+            },
+            // A reply that was not streamed
+            (Activity::Waiting { sent }, ChatUpdate::StreamFinished) => Activity::Done {
+                total: now - sent,
+                first_token: now - sent,
+                tokens: 0,
+                rate: None,
+            },
+            (_, ChatUpdate::RequestError(_)) => Activity::Idle,
+            (activity, _) => activity,
+        };
+    }
+
+    /// Set the tokens of the reply streamed so far
+    pub fn reply(&mut self, streamed: usize) {
+        if let Activity::Streaming { tokens, .. } = self {
+            *tokens = streamed;
+        }
+    }
+
+    /// Whether the indicator moves, to be drawn again on each frame of the spinner
+    pub fn is_running(&self) -> bool {
+        matches!(self, Activity::Waiting { .. } | Activity::Streaming { .. })
+    }
+
+    pub fn line(&self) -> Line<'static> {
+        match *self {
+            Activity::Idle => Line::default(),
+            Activity::Waiting { sent } => Line::raw(format!(
+                "{} Waiting {}",
+                spinner(sent),
+                seconds(sent.elapsed())
+            )),
+            Activity::Streaming {
+                sent,
+                first_token,
+                tokens,
+            } => {
+                let mut text = format!(
+                    "{} Streaming {}, first token after {}",
+                    spinner(sent),
+                    seconds(sent.elapsed()),
+                    seconds(first_token - sent)
+                );
+                if let Some(rate) = rate(tokens, first_token.elapsed()) {
+                    text.push_str(&format!(", {:.1} tokens/s", rate));
+                }
+                Line::raw(text)
+            }
+            Activity::Done {
+                total,
+                first_token,
+                tokens,
+                rate,
+            } => {
+                let mut text = format!(
+                    "Done in {}, first token after {}, {} tokens",
+                    seconds(total),
+                    seconds(first_token),
+                    tokens
+                );
+                if let Some(rate) = rate {
+                    text.push_str(&format!(" at {:.1} tokens/s", rate));
+                }
+                Line::styled(text, Color::DarkGray)
+            }
+        }
+    }
+}
+
+fn spinner(start: Instant) -> char {
+    let frame = start.elapsed().as_millis() / SPINNER_PERIOD.as_millis();
+    SPINNER[frame as usize % SPINNER.len()]
+}
+
+fn seconds(duration: Duration) -> String {
+    format!("{:.1}s", duration.as_secs_f64())
+}
+
+/// Tokens per second, when they came over a long enough time for the rate to mean something
+fn rate(tokens: usize, duration: Duration) -> Option<f64> {
+    match duration >= SPINNER_PERIOD {
+        true => Some(tokens as f64 / duration.as_secs_f64()),
+        false => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_the_tokens_counted_from_the_reply() {
+        let mut activity = Activity::default();
+        activity.update(&ChatUpdate::RequestSent);
+        activity.reply(5); // Not streaming yet
+        activity.update(&ChatUpdate::StreamUpdate);
+        activity.update(&ChatUpdate::StreamUpdate);
+        activity.reply(12);
+        activity.update(&ChatUpdate::StreamFinished);
+        assert!(matches!(activity, Activity::Done { tokens: 12, .. }));
+        activity.reply(20); // Frozen once finished
+        assert!(matches!(activity, Activity::Done { tokens: 12, .. }));
+    }
+}
@@ -1,24 +1,40 @@
-use arboard::Clipboard;
-use crossterm::event::{Event, KeyCode, KeyEvent};
+use std::{cell::RefCell, collections::HashMap, io, rc::Rc, sync::Arc};
+
+use crossterm::event::{Event, KeyCode, KeyEvent, KeyModifiers};
 use libmoon::{
     chat::{Chat, ChatUpdate},
     gateway::GatewayUpdate,
-    message::Message,
+    message::{self, Message},
     moon::MoonUpdate,
+    persona::Persona,
 };
 use ratatui::{
-    layout::{Alignment, Constraint, Layout},
+    buffer::Buffer,
+    layout::{Alignment, Constraint, Layout, Rect},
     style::{Color, Style},
     text::{Line, Span},
     widgets::{Block, Borders, Paragraph, StatefulWidget, Widget, Wrap},
 };
+use tokio::sync::Mutex;
 use tui_widget_list::{ListBuilder, ListState, ListView};
 
+use self::cache::MessageCache;
+
 use crate::{
     AppCommand,
-    editor_widget::{EditorResult, EditorState, EditorUnfocused, EditorWidget},
+    activity::Activity,
+    config,
+    drafts::Drafts,
+    editor_widget::{
+        EditorResult, EditorState, EditorUnfocused, EditorWidget, History, Tokenizer,
+        configured_tokenizer,
+    },
+    input_history::InputHistory,
+    macros, registers,
 };
 
+mod cache;
+
 enum Mode {
     Normal,
     Editing(Box<EditorState>),
@@ -27,77 +43,281 @@ enum Mode {
 }
 
 pub struct ChatState {
-    title: String,
+    title: String, // Name of the persona the chat is with, which keys its input history and drafts
     history: Vec<Message>,
     structure: Vec<(usize, usize)>,
+    cache: RefCell<MessageCache>, // Lines and heights of the messages, filled as they are drawn
+    reply_changed: bool, // The reply streamed in since the history was last copied from the chat
     input_mode: Mode,
     list_state: ListState,
     editor_state: EditorState,
+    input_history: InputHistory, // Messages sent to the current persona
+    drafts: Drafts, // Unsent texts of the editors, restored when coming back to the persona
+    edit_histories: HashMap<Vec<usize>, History>, // Undo histories of the messages edited with this persona
     status: Option<&'static str>,
+    activity: Activity, // Progress of the reply, shown when there is no status
+    tokenizer: Box<dyn Tokenizer>, // Estimates the tokens of the reply as it streams in
+    reply_start: (Vec<usize>, usize), // Last message when the request was sent and its tokens, for a continued reply
     borders: bool,
+    maximized: bool, // The input editor takes the whole screen
+    preview: bool,   // The draft is shown as it will be sent, with the macros expanded
+    personas: Arc<Mutex<Vec<Persona>>>,
+}
+
+/// Height of the input area with an empty editor
+const INPUT_HEIGHT: u16 = 5;
+
+/// Style of the text of a message, shared with the editors where messages are written
+pub fn message_style(style: message::Style) -> Style {
+    let color = match style {
+        message::Style::Normal => Color::White,
+        message::Style::Strong => Color::Blue,
+        message::Style::Quote => Color::Red,
+        message::Style::StrongQuote => Color::Green,
+    };
+    Style::default().fg(color)
 }
 
 impl ChatState {
-    pub fn new(title: String, history: Vec<Message>, structure: Vec<(usize, usize)>) -> Self {
+    pub fn new(
+        title: String,
+        history: Vec<Message>,
+        structure: Vec<(usize, usize)>,
+        personas: Arc<Mutex<Vec<Persona>>>,
+    ) -> Self {
         let mut list_state = ListState::default();
         if !history.is_empty() {
             list_state.selected = Some(0);
         }
-        ChatState {
+        let input_history = InputHistory::load(&title);
+        let drafts = Drafts::load(&title);
+        let mut chat_state = ChatState {
             title,
             history,
             structure,
+            cache: RefCell::default(),
+            reply_changed: false,
             input_mode: Mode::Normal,
             list_state,
             editor_state: EditorState::default(),
+            input_history,
+            drafts,
+            edit_histories: HashMap::new(),
             status: None,
+            activity: Activity::default(),
+            tokenizer: configured_tokenizer(),
+            reply_start: (Vec::new(), 0),
             borders: true,
+            maximized: false,
+            preview: false,
+            personas,
+        };
+        chat_state.restore_draft();
+        chat_state.drop_stale_edit_draft();
+        chat_state
+    }
+
+    /// Keep the draft of the current persona, then load the messages sent to the new one, for the input editor
+    /// to recall them, and its draft
+    pub fn set_persona(&mut self, name: &str) {
+        self.autosave();
+        self.title = name.to_string();
+        self.edit_histories.clear();
+        self.input_history = InputHistory::load(&self.title);
+        self.drafts = Drafts::load(&self.title);
+        self.restore_draft();
+    }
+
+    /// Write the unsent texts of the input editor and of the message being edited to the drafts of the persona
+    pub fn autosave(&mut self) {
+        let edit = match &self.input_mode {
+            Mode::Editing(editor_state) => Some((
+                self.message_id(self.list_state.selected.unwrap_or(0)),
+                editor_state.text(),
+            )),
+            _ => None,
+        };
+        let edit = edit
+            .as_ref()
+            .map(|(message, text)| (message.as_slice(), text.as_str()));
+        self.drafts.save(&self.editor_state.text(), edit);
+    }
+
+    /// Forget the draft of an edit when its message is not in the chat shown any more
+    fn drop_stale_edit_draft(&mut self) {
+        if let Some(message) = self.drafts.edited()
+            && (message.is_empty() || self.message_id(message.len() - 1) != message)
+        {
+            self.drafts.drop_edit();
         }
     }
 
+    /// The message at `index` among all the variants: the variant shown of each message up to it
+    fn message_id(&self, index: usize) -> Vec<usize> {
+        self.structure
+            .iter()
+            .take(index + 1)
+            .map(|&(variant, _)| variant)
+            .collect()
+    }
+
+    /// Put the draft left for the persona in a new input editor
+    fn restore_draft(&mut self) {
+        let draft = self.drafts.input().map(str::to_string);
+        self.editor_state = self.composer(draft.clone().unwrap_or_default());
+        if draft.is_some() {
+            self.editor_state.set_message("Restored draft");
+        }
+    }
+
+    /// Macros and persona names completed in the editors
+    fn completions(&self) -> Vec<String> {
+        let mut completions: Vec<String> = macros::MACROS.iter().map(|m| m.to_string()).collect();
+        if let Ok(personas) = self.personas.try_lock() {
+            completions.extend(personas.iter().map(|p| p.name().to_string()));
+        }
+        completions
+    }
+
+    /// New input editor with the text, and the sent messages to recall
+    fn composer(&self, text: String) -> EditorState {
+        let mut editor_state = EditorState::new(text, false);
+        editor_state.set_recall(self.input_history.entries().to_vec());
+        editor_state.set_tokenizer(configured_tokenizer());
+        editor_state.set_display(self.editor_state.display()); // Keep what was changed with :set
+        editor_state
+    }
+
     pub fn update_status(&mut self, status: MoonUpdate, chat: &Chat) {
-        self.status = Some(match status {
-            MoonUpdate::CU(cu) => match cu {
-                ChatUpdate::RequestSent => "Waiting",
-                ChatUpdate::RequestOk => "OK",
-                ChatUpdate::StreamUpdate => "Streaming",
-                ChatUpdate::StreamFinished => "Done",
-                ChatUpdate::RequestError(_) => "API Error",
-            },
-            MoonUpdate::GU(gu) => match gu {
+        match status {
+            // Copied from the chat with the next frame, once for all the updates coming until then
+            MoonUpdate::CU(ChatUpdate::StreamUpdate) => self.reply_changed = true,
+            // Before the activity is updated, for a finished reply to be counted in full
+            _ => self.update_history(chat),
+        }
+        self.status = match status {
+            MoonUpdate::CU(cu) => {
+                if let ChatUpdate::RequestSent = cu {
+                    self.reply_start = self.last_message_size();
+                }
+                self.activity.update(&cu);
+                match cu {
+                    ChatUpdate::RequestError(_) => Some("API Error"),
+                    _ => None,
+                }
+            }
+            MoonUpdate::GU(gu) => Some(match gu {
                 GatewayUpdate::Char => "Char loaded",
                 GatewayUpdate::User => "User loaded",
-            },
-            MoonUpdate::Error(_) => "Moon Error",
-        });
-        self.history = chat.get_history();
-        self.structure = chat.get_history_structure();
+            }),
+            MoonUpdate::Error(_) => Some("Moon Error"),
+        };
+    }
+
+    /// Bring the reply streaming in up to date before drawing a frame. `Chat` only hands out a copy of the whole
+    /// history, so the chat is still copied once per frame while a reply streams in, but not once per update.
+    pub fn refresh(&mut self, chat: &Chat) {
+        if !self.reply_changed {
+            return;
+        }
+        match self.take_reply(chat.get_history()) {
+            true => self.count_reply(),
+            false => self.update_history(chat),
+        }
+        self.reply_changed = false;
+    }
+
+    /// Estimate the tokens streamed into the reply since the request was sent, from the growth of its text
+    fn count_reply(&mut self) {
+        if !self.activity.is_running() {
+            return;
+        }
+        let (message, tokens) = self.last_message_size();
+        let (start, before) = &self.reply_start;
+        let before = match message == *start {
+            true => *before, // A continued message
+            false => 0,
+        };
+        self.activity.reply(tokens.saturating_sub(before));
+    }
+
+    /// Last message of the chat and its tokens
+    fn last_message_size(&self) -> (Vec<usize>, usize) {
+        match self.history.last() {
+            Some(message) => (
+                self.message_id(self.history.len() - 1),
+                self.tokenizer.count(&message.text),
+            ),
+            None => (Vec::new(), 0),
+        }
+    }
+
+    /// Whether a reply is awaited, with an indicator to animate
+    pub fn busy(&self) -> bool {
+        self.activity.is_running()
     }
 
     pub fn input(&mut self, event: Event, chat: &mut Chat) -> AppCommand {
+        // A key doing nothing still changes the screen when it clears the status or the preview
+        let clears = self.status.is_some() || self.preview;
         self.status = None;
+        // Keys act on the messages as they are, not as they were at the last frame
+        self.refresh(chat);
+        if let Event::Paste(_) = event {
+            match &mut self.input_mode {
+                Mode::Inputing => _ = self.editor_state.input(event),
+                Mode::Editing(editor_state) => _ = editor_state.input(event),
+                _ => (),
+            }
+            return AppCommand::None;
+        }
         if let Event::Key(key) = event {
+            let external_editor =
+                key.code == KeyCode::Char('x') && key.modifiers.contains(KeyModifiers::CONTROL);
+            let maximize =
+                key.code == KeyCode::Char('z') && key.modifiers.contains(KeyModifiers::ALT);
+            let preview =
+                key.code == KeyCode::Char('p') && key.modifiers.contains(KeyModifiers::ALT);
+            if !preview {
+                self.preview = false;
+            }
             match &mut self.input_mode {
                 Mode::Normal => match key.code {
-                    KeyCode::Char('i') => self.input_mode = Mode::Inputing,
+                    KeyCode::Char('i') => {
+                        self.editor_state.set_completions(self.completions());
+                        self.input_mode = Mode::Inputing;
+                    }
                     KeyCode::Char('b') => self.borders = !self.borders,
                     KeyCode::Char('s') => return AppCommand::ToggleSelection,
+                    KeyCode::Char('E') => {
+                        return AppCommand::ExternalEditor(self.editor_state.text());
+                    }
                     KeyCode::Esc => self.input_mode = Mode::Quiting,
-                    _ => self.update(&key, chat),
+                    _ => {
+                        if !self.update(&key, chat) && !clears {
+                            return AppCommand::Ignored;
+                        }
+                    }
                 },
+                Mode::Inputing if external_editor => {
+                    return AppCommand::ExternalEditor(self.editor_state.text());
+                }
+                Mode::Inputing if maximize => self.maximized = !self.maximized,
+                Mode::Inputing | Mode::Editing(_) if preview => self.preview = !self.preview,
+                Mode::Editing(editor_state) if external_editor => {
+                    return AppCommand::ExternalEditor(editor_state.text());
+                }
                 Mode::Inputing => match self.editor_state.input(event) {
-                    EditorResult::Ok => self.chat_push(chat),
+                    EditorResult::Ok => self.chat_push(chat, self.editor_state.text()),
                     EditorResult::Quit => self.input_mode = Mode::Normal,
                     _ => (),
                 },
                 Mode::Editing(editor_state) => match editor_state.input(event) {
                     EditorResult::Ok => {
-                        chat.add_edit(self.list_state.selected.unwrap_or(0), editor_state.text());
-                        self.input_mode = Mode::Normal;
-                        self.update_history(chat);
-                        self.selected_to_last();
+                        let text = editor_state.text();
+                        self.edit_push(chat, text);
                     }
-                    EditorResult::Quit => self.input_mode = Mode::Normal,
+                    EditorResult::Quit => self.close_edit(),
                     _ => (),
                 },
                 Mode::Quiting => match key.code {
@@ -109,7 +329,8 @@ impl ChatState {
         AppCommand::None
     }
 
-    pub fn update(&mut self, event: &KeyEvent, chat: &mut Chat) {
+    /// Act on a key of the normal mode on the selected message, returning false when the key does nothing
+    pub fn update(&mut self, event: &KeyEvent, chat: &mut Chat) -> bool {
         if let Some(depth) = self.list_state.selected {
             match event.code {
                 KeyCode::Char('h') | KeyCode::Left => self.chat_prev(chat, depth),
@@ -121,29 +342,103 @@ impl ChatState {
                 KeyCode::Char('e') => {
                     let selected = self.list_state.selected.unwrap_or(0);
                     if self.history.len() > selected {
-                        let edit_text = self.history[selected].text.clone();
-                        self.input_mode =
-                            Mode::Editing(Box::new(EditorState::new(edit_text, false)))
+                        let text = &self.history[selected].text;
+                        let draft = self
+                            .drafts
+                            .edit(&self.message_id(selected))
+                            .filter(|draft| *draft != text.trim_end_matches('\n'));
+                        let mut editor_state =
+                            EditorState::new(draft.unwrap_or(text).to_string(), false);
+                        editor_state.set_tokenizer(configured_tokenizer());
+                        editor_state.set_display(self.editor_state.display());
+                        if draft.is_some() {
+                            editor_state.set_message("Restored draft");
+                        }
+                        let history = self.edit_histories.remove(&self.message_id(selected));
+                        if let Some(history) = history {
+                            editor_state.set_history(history);
+                        }
+                        editor_state.set_completions(self.completions());
+                        self.input_mode = Mode::Editing(Box::new(editor_state))
                     }
                 }
-                _ => (),
+                _ => return false,
             }
+            return true;
         }
+        false
     }
 
     pub fn update_list(&mut self, chat: &Chat) {
         self.update_history(chat);
         self.selected_to_last();
+        self.drop_stale_edit_draft(); // The chat of another persona
     }
 
-    fn chat_push(&mut self, chat: &mut Chat) {
-        chat.add_user_message(self.editor_state.text());
-        self.editor_state = EditorState::default();
+    /// Use the text written in the external editor as the new message, or as the edited one. Nothing is sent when the
+    /// editor was left without changing `original` or with nothing written.
+    pub fn external_edit(&mut self, result: io::Result<String>, original: &str, chat: &mut Chat) {
+        let text = match result {
+            Ok(text) => text,
+            Err(_) => {
+                self.status = Some("Editor error");
+                return;
+            }
+        };
+        if text.trim().is_empty() {
+            self.status = Some("Empty message, not sent");
+            return;
+        }
+        if text.trim_end() == original.trim_end() {
+            self.status = Some("Message unchanged, not sent");
+            return;
+        }
+        match self.input_mode {
+            Mode::Editing(_) => self.edit_push(chat, text),
+            _ => self.chat_push(chat, text),
+        }
+    }
+
+    fn chat_push(&mut self, chat: &mut Chat, text: String) {
+        self.input_history.push(&text);
+        chat.add_user_message(text);
+        // Undo in the emptied editor brings back the sent message
+        let history = self.editor_state.take_history();
+        self.editor_state = self.composer(String::new());
+        self.editor_state.set_history(history);
+        self.maximized = false;
         self.input_mode = Mode::Normal;
+        self.autosave();
         self.update_history(chat);
         self.selected_to_last();
     }
 
+    fn edit_push(&mut self, chat: &mut Chat, text: String) {
+        let index = self.list_state.selected.unwrap_or(0);
+        chat.add_edit(index, text);
+        self.close_edit();
+        // The edit is a new variant of the message, the one its undo history goes on with
+        let history = self.edit_histories.remove(&self.message_id(index));
+        self.update_history(chat);
+        if let Some(history) = history {
+            self.edit_histories.insert(self.message_id(index), history);
+        }
+        self.selected_to_last();
+    }
+
+    /// Leave the editor of the selected message, keeping its undo history for the next time it is edited
+    fn close_edit(&mut self) {
+        let message = self.message_id(self.list_state.selected.unwrap_or(0));
+        if let Mode::Editing(editor_state) = &mut self.input_mode {
+            self.edit_histories
+                .insert(message, editor_state.take_history());
+            self.editor_state.set_display(editor_state.display());
+        }
+        self.drafts.drop_edit();
+        self.input_mode = Mode::Normal;
+        self.autosave();
+    }
+
     fn chat_next(&mut self, chat: &mut Chat, depth: usize) {
         chat.next(depth);
         self.update_history(chat);
@@ -160,9 +455,24 @@ impl ChatState {
         self.selected_to_last();
     }
 
+    /// Take the reply streaming in from a copy of the history, keeping the other messages and their layout as they
+    /// are. False when the history is not the one shown with a longer reply, to be updated in full.
+    fn take_reply(&mut self, mut history: Vec<Message>) -> bool {
+        if history.len() != self.history.len() {
+            return false;
+        }
+        if let (Some(last), Some(reply)) = (self.history.last_mut(), history.pop()) {
+            *last = reply;
+        }
+        true
+    }
+
     fn update_history(&mut self, chat: &Chat) {
         self.history = chat.get_history();
         self.structure = chat.get_history_structure();
+        self.cache.get_mut().truncate(self.history.len());
+        self.reply_changed = false;
+        self.count_reply();
     }
 
     fn selected_to_last(&mut self) {
@@ -173,30 +483,62 @@ impl ChatState {
     }
 
     fn message_to_clipboard(&mut self, chat: &Chat, depth: usize) {
-        if let Ok(mut clipboard) = Clipboard::new() {
-            let history = chat.get_history();
-            match clipboard.set_text(history[depth].clean()) {
-                Ok(_) => self.status = Some("Yanked"),
-                Err(_) => self.status = Some("Copy error"),
-            }
+        let history = chat.get_history();
+        match registers::set('+', history[depth].clean()) {
+            Ok(_) => self.status = Some("Yanked"),
+            Err(_) => self.status = Some("Copy error"),
         }
     }
 
+    /// Height of the input area, growing with the text as it is wrapped up to the configured fraction of the screen
+    fn input_height(&self, area: Rect) -> u16 {
+        let max = (area.height as f32 * config::get().input_height) as u16;
+        let rows = self.editor_state.display_rows(area.width);
+        let height = (rows.min(u16::MAX as usize) as u16).saturating_add(2); // With the borders
+        height.min(max).max(INPUT_HEIGHT)
+    }
+
+    /// Text of the focused editor as it will be sent, shown in place of the editor when the preview is toggled
+    fn preview(&self) -> Option<Paragraph<'static>> {
+        let text = match &self.input_mode {
+            Mode::Editing(editor_state) if self.preview => editor_state.text(),
+            Mode::Inputing if self.preview => self.editor_state.text(),
+            _ => return None,
+        };
+        let text = macros::expand(&text, &self.title, None); // libmoon gives no user persona to the interface
+        let title = match macros::has_user(&text) {
+            true => "PREVIEW, {{user}} is not known here (type Alt-P to go back to the draft)",
+            false => "PREVIEW (type Alt-P to go back to the draft)",
+        };
+        Some(
+            Paragraph::new(text)
+                .wrap(Wrap { trim: false })
+                .block(Block::bordered().title(title)),
+        )
+    }
+
     fn render_list(&mut self, area: ratatui::prelude::Rect, buf: &mut ratatui::prelude::Buffer) {
         let builder = ListBuilder::new(|context| {
-            let item = Self::paragraph(
-                &self.history[context.index],
-                self.structure[context.index],
-                context.is_selected,
+            let message = &self.history[context.index];
+            let look = (self.structure[context.index], context.is_selected);
+            let mut cache = self.cache.borrow_mut();
+            let paragraph = cache.paragraph(
+                context.index,
+                &message.text,
+                look,
+                || Self::lines(message),
+                |lines| Self::paragraph(message, lines, look.0, look.1),
             );
-            let main_axis_size = item.line_count(area.width - 2) as u16;
-            (item, main_axis_size)
+            let width = area.width - 2;
+            let main_axis_size =
+                cache.height(context.index, width, || paragraph.line_count(width) as u16);
+            (CachedMessage(paragraph), main_axis_size)
         });
 
-        let status = Line::from(match &self.status {
-            Some(status) => status,
-            None => "",
-        })
+        let status = match self.status {
+            Some(status) => Line::from(status),
+            None => self.activity.line(),
+        }
         .alignment(Alignment::Right);
         let item_count = self.history.len();
         let borders = match self.borders {
@@ -216,23 +558,33 @@ impl ChatState {
         list.render(area, buf, &mut self.list_state);
     }
 
-    fn paragraph(message: &Message, structure: (usize, usize), selected: bool) -> Paragraph<'_> {
-        let style = match selected {
-            true => Style::new().fg(ratatui::style::Color::Red),
-            false => Style::new(),
-        };
-
-        let title = Line::from(message.owner_name.clone()).centered();
-        let structure = Line::from(format!("{}/{}", structure.0, structure.1)).right_aligned();
+    /// Lines of a message styled after its markup, a blank line after each
+    fn lines(message: &Message) -> Vec<Line<'static>> {
         let mut lines = vec![];
         for l in message.spans() {
             let spans: Vec<Span> = l
                 .into_iter()
-                .map(|(text, style)| Span::from(text).style(Self::color(style)))
+                .map(|(text, style)| Span::from(text).style(message_style(style)))
                 .collect();
             lines.push(Line::from(spans));
             lines.push(Line::from(""));
         }
+        lines
+    }
+
+    fn paragraph(
+        message: &Message,
+        lines: Vec<Line<'static>>,
+        structure: (usize, usize),
+        selected: bool,
+    ) -> Paragraph<'static> {
+        let style = match selected {
+            true => Style::new().fg(ratatui::style::Color::Red),
+            false => Style::new(),
+        };
+
+        let title = Line::from(message.owner_name.clone()).centered();
+        let structure = Line::from(format!("{}/{}", structure.0, structure.1)).right_aligned();
         Paragraph::new(lines).wrap(Wrap { trim: true }).block(
             Block::bordered()
                 .borders(Borders::TOP)
@@ -241,15 +593,14 @@ impl ChatState {
                 .title(structure),
         )
     }
+}
 
-    fn color(style: libmoon::message::Style) -> Style {
-        let color = match style {
-            libmoon::message::Style::Normal => Color::White,
-            libmoon::message::Style::Strong => Color::Blue,
-            libmoon::message::Style::Quote => Color::Red,
-            libmoon::message::Style::StrongQuote => Color::Green,
-        };
-        Style::default().fg(color)
+/// Message of the list, drawn from the paragraph kept in the cache
+struct CachedMessage(Rc<Paragraph<'static>>);
+
+impl Widget for CachedMessage {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        self.0.as_ref().render(area, buf);
     }
 }
 
@@ -265,19 +616,30 @@ impl StatefulWidget for ChatWidget {
         buf: &mut ratatui::prelude::Buffer,
         state: &mut Self::State,
     ) {
-        match &mut state.input_mode {
-            Mode::Editing(editor_state) => EditorWidget::default().render(area, buf, editor_state),
-            Mode::Quiting => {
+        let preview = state.preview();
+        match (&mut state.input_mode, preview) {
+            (Mode::Editing(_), Some(preview)) => preview.render(area, buf),
+            (Mode::Editing(editor_state), None) => {
+                EditorWidget::default().render(area, buf, editor_state)
+            }
+            (Mode::Inputing, Some(preview)) if state.maximized => preview.render(area, buf),
+            (Mode::Inputing, None) if state.maximized => {
+                EditorWidget::default().render(area, buf, &mut state.editor_state)
+            }
+            (Mode::Quiting, _) => {
                 Line::from("Quit? (Enter)")
                     .alignment(Alignment::Center)
                     .render(area, buf);
             }
-            _ => {
-                let vertical = Layout::vertical([Constraint::Min(1), Constraint::Length(5)]);
+            (_, preview) => {
+                let input_height = state.input_height(area);
+                let vertical =
+                    Layout::vertical([Constraint::Min(1), Constraint::Length(input_height)]);
                 let [messages_area, input_area] = vertical.areas(area);
                 state.render_list(messages_area, buf);
-                match state.input_mode {
-                    Mode::Inputing => {
+                match (&state.input_mode, preview) {
+                    (_, Some(preview)) => preview.render(input_area, buf),
+                    (Mode::Inputing, None) => {
                         EditorWidget::default().render(input_area, buf, &mut state.editor_state)
                     }
                     _ => {
@@ -288,3 +650,60 @@ impl StatefulWidget for ChatWidget {
         };
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MESSAGES: usize = 10;
+
+    /// Paragraph kept for the message at `index` as it is shown, failing if it would be parsed or laid out again
+    fn kept(chat_state: &ChatState, index: usize) -> Rc<Paragraph<'static>> {
+        let selected = chat_state.list_state.selected == Some(index);
+        let look = (chat_state.structure[index], selected);
+        chat_state.cache.borrow_mut().paragraph(
+            index,
+            &chat_state.history[index].text,
+            look,
+            || panic!("message {} was not kept", index),
+            |_| panic!("message {} was not kept laid out", index),
+        )
+    }
+
+    #[test]
+    fn a_streamed_reply_only_lays_out_the_reply_again() {
+        let mut history: Vec<Message> = (0..MESSAGES)
+            .map(|i| Message {
+                text: format!("*looks at message {}* \"Hello,\" she says.", i),
+                owner_name: ["Ann", "Bob"][i % 2].to_string(),
+            })
+            .collect();
+        let mut chat_state = ChatState::new(
+            "halfmoon".to_string(),
+            history.clone(),
+            vec![(1, 1); MESSAGES],
+            Arc::default(),
+        );
+        chat_state.list_state.selected = Some(MESSAGES - 1);
+        let area = Rect::new(0, 0, 80, 60);
+        let mut buf = Buffer::empty(area);
+        chat_state.render_list(area, &mut buf);
+        let before: Vec<_> = (0..MESSAGES).map(|i| kept(&chat_state, i)).collect();
+
+        if let Some(reply) = history.last_mut() {
+            reply.text.push_str(" *nods*");
+        }
+        assert!(chat_state.take_reply(history));
+        chat_state.render_list(area, &mut buf);
+        let after: Vec<_> = (0..MESSAGES).map(|i| kept(&chat_state, i)).collect();
+
+        for i in 0..MESSAGES - 1 {
+            assert!(
+                Rc::ptr_eq(&before[i], &after[i]),
+                "message {} laid out again",
+                i
+            );
+        }
+        assert!(!Rc::ptr_eq(&before[MESSAGES - 1], &after[MESSAGES - 1]));
+    }
+}
@@ -0,0 +1,165 @@
+// Messages of the chat laid out for the list, kept between frames. Parsing and wrapping every visible message on
+// every frame is too slow for long chats while a reply streams in, so a message is parsed again only when its text
+// changes, laid out again only when its text or its look changes, and wrapped again only when its text or the width
+// changes. The list draws the kept paragraphs without copying them.
+
+use std::rc::Rc;
+
+use ratatui::{text::Line, widgets::Paragraph};
+
+/// What a message looks like besides its text: its variant among the ones of its place, and whether it is selected
+pub type Look = ((usize, usize), bool);
+
+struct Entry {
+    text: String, // Text the lines were made from
+    lines: Vec<Line<'static>>,
+    paragraph: Option<(Look, Rc<Paragraph<'static>>)>,
+    height: Option<(u16, u16)>, // Width the lines were wrapped at, and their height
+}
+
+#[derive(Default)]
+pub struct MessageCache {
+    entries: Vec<Option<Entry>>,
+}
+
+impl MessageCache {
+    /// Paragraph of the message at `index`, laid out by `layout` from the lines made by `parse`. The lines are made
+    /// again when the message is new or its text changed, and the paragraph when its lines or its look changed.
+    pub fn paragraph(
+        &mut self,
+        index: usize,
+        text: &str,
+        look: Look,
+        parse: impl FnOnce() -> Vec<Line<'static>>,
+        layout: impl FnOnce(Vec<Line<'static>>) -> Paragraph<'static>,
+    ) -> Rc<Paragraph<'static>> {
+        if self.entries.len() <= index {
+            self.entries.resize_with(index + 1, || None);
+        }
+        let slot = &mut self.entries[index];
+        let entry = match slot.take() {
+            Some(entry) if entry.text == text => slot.insert(entry),
+            _ => slot.insert(Entry {
+                text: text.to_string(),
+                lines: parse(),
+                paragraph: None,
+                height: None,
+            }),
+        };
+        match &entry.paragraph {
+            Some((shown, paragraph)) if *shown == look => Rc::clone(paragraph),
+            _ => {
+                let paragraph = Rc::new(layout(entry.lines.clone()));
+                entry.paragraph = Some((look, Rc::clone(&paragraph)));
+                paragraph
+            }
+        }
+    }
+
+    /// Height of the message at `index` at `width`, counted by `count` when the width or the text changed since the
+    /// last time. The paragraph of the message must have been asked for first.
+    pub fn height(&mut self, index: usize, width: u16, count: impl FnOnce() -> u16) -> u16 {
+        let Some(Some(entry)) = self.entries.get_mut(index) else {
+            return count();
+        };
+        match entry.height {
+            Some((counted_width, height)) if counted_width == width => height,
+            _ => {
+                let height = count();
+                entry.height = Some((width, height));
+                height
+            }
+        }
+    }
+
+    /// Forget the messages after the first `len`, when the chat got shorter
+    pub fn truncate(&mut self, len: usize) {
+        self.entries.truncate(len);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use super::*;
+
+    const LOOK: Look = ((1, 1), false);
+
+    /// Paragraph of `text` from the cache, counting the times it was parsed and laid out
+    fn paragraph(
+        cache: &mut MessageCache,
+        text: &str,
+        look: Look,
+        parsed: &Cell<usize>,
+        laid_out: &Cell<usize>,
+    ) -> Rc<Paragraph<'static>> {
+        cache.paragraph(
+            0,
+            text,
+            look,
+            || {
+                parsed.set(parsed.get() + 1);
+                vec![Line::raw(text.to_string())]
+            },
+            |lines| {
+                laid_out.set(laid_out.get() + 1);
+                Paragraph::new(lines)
+            },
+        )
+    }
+
+    #[test]
+    fn parses_again_only_when_the_text_changes() {
+        let mut cache = MessageCache::default();
+        let (parsed, laid_out) = (Cell::new(0), Cell::new(0));
+        let first = paragraph(&mut cache, "hello", LOOK, &parsed, &laid_out);
+        let second = paragraph(&mut cache, "hello", LOOK, &parsed, &laid_out);
+        assert!(Rc::ptr_eq(&first, &second));
+        assert_eq!((parsed.get(), laid_out.get()), (1, 1));
+        paragraph(&mut cache, "hello there", LOOK, &parsed, &laid_out);
+        assert_eq!((parsed.get(), laid_out.get()), (2, 2));
+    }
+
+    #[test]
+    fn lays_out_again_when_the_look_changes() {
+        let mut cache = MessageCache::default();
+        let (parsed, laid_out) = (Cell::new(0), Cell::new(0));
+        paragraph(&mut cache, "hello", LOOK, &parsed, &laid_out);
+        paragraph(&mut cache, "hello", ((1, 1), true), &parsed, &laid_out);
+        paragraph(&mut cache, "hello", ((2, 2), true), &parsed, &laid_out);
+        assert_eq!((parsed.get(), laid_out.get()), (1, 3));
+    }
+
+    #[test]
+    fn counts_the_height_again_when_the_width_or_text_changes() {
+        let mut cache = MessageCache::default();
+        let (parsed, laid_out) = (Cell::new(0), Cell::new(0));
+        let counted = Cell::new(0);
+        let height = |cache: &mut MessageCache, width| {
+            cache.height(0, width, || {
+                counted.set(counted.get() + 1);
+                3
+            })
+        };
+        paragraph(&mut cache, "hello", LOOK, &parsed, &laid_out);
+        assert_eq!(height(&mut cache, 80), 3);
+        height(&mut cache, 80);
+        assert_eq!(counted.get(), 1);
+        height(&mut cache, 40);
+        assert_eq!(counted.get(), 2);
+        paragraph(&mut cache, "hello there", LOOK, &parsed, &laid_out);
+        height(&mut cache, 40);
+        assert_eq!(counted.get(), 3);
+    }
+
+    #[test]
+    fn forgets_the_messages_cut_off() {
+        let mut cache = MessageCache::default();
+        let (parsed, laid_out) = (Cell::new(0), Cell::new(0));
+        paragraph(&mut cache, "hello", LOOK, &parsed, &laid_out);
+        cache.truncate(0);
+        paragraph(&mut cache, "hello", LOOK, &parsed, &laid_out);
+        assert_eq!(parsed.get(), 2);
+    }
+}
@@ -0,0 +1,209 @@
+// User settings, read once from $XDG_CONFIG_HOME/halfmoon/config.toml (~/.config/halfmoon/config.toml by default).
+// Only flat `key = value` lines are understood, which is all the settings need. Unknown keys and values are ignored.
+// Files written by the application go to $XDG_DATA_HOME/halfmoon (~/.local/share/halfmoon by default).
+
+use std::{env, fs, path::PathBuf, sync::LazyLock};
+
+static CONFIG: LazyLock<Config> = LazyLock::new(load);
+
+#[derive(Default, Clone, Copy, PartialEq)]
+pub enum EditingProfile {
+    #[default]
+    Vim,
+    Emacs, // Modeless editing with readline key bindings
+}
+
+/// How the lines of the editors are numbered in the gutter
+#[derive(Default, Clone, Copy, PartialEq)]
+pub enum LineNumbers {
+    #[default]
+    Off,
+    Absolute,
+    Relative, // Distance to the cursor line, which shows its own number
+}
+
+pub struct Config {
+    pub editing: EditingProfile,
+    pub token_warning: Option<usize>, // Size of a message in tokens above which the editor border turns red
+    pub chars_per_token: Option<f32>, // Fixed ratio estimating the tokens instead of the default heuristic
+    pub input_height: f32,            // Fraction of the screen the input area can grow to
+    pub auto_pairs: bool,             // Close brackets, asterisks and quotes when typing them
+    pub wrap: bool,                   // Wrap the long lines of the editors
+    pub line_numbers: LineNumbers,
+    pub max_fps: u32, // Frames drawn per second at most while a reply streams in
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            editing: EditingProfile::default(),
+            token_warning: None,
+            chars_per_token: None,
+            input_height: 0.5,
+            auto_pairs: false,
+            wrap: true,
+            line_numbers: LineNumbers::default(),
+            max_fps: 30,
+        }
+    }
+}
+
+pub fn get() -> &'static Config {
+    &CONFIG
+}
+
+/// Directory from an XDG variable, or from its default under the home directory
+fn xdg_dir(variable: &str, default: &str) -> Option<PathBuf> {
+    let dir = match env::var_os(variable) {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var_os("HOME")?).join(default),
+    };
+    Some(dir.join("halfmoon"))
+}
+
+pub fn data_dir() -> Option<PathBuf> {
+    xdg_dir("XDG_DATA_HOME", ".local/share")
+}
+
+/// File of a persona in a subdirectory of the data directory. The characters of its name that are not letters,
+/// digits or '-' are percent-encoded, so that the name is safe in a path and two personas never share a file.
+pub fn persona_file(subdir: &str, persona: &str) -> Option<PathBuf> {
+    let mut name = String::new();
+    for c in persona.chars() {
+        match c.is_alphanumeric() || c == '-' {
+            true => name.push(c),
+            false => {
+                for byte in c.encode_utf8(&mut [0; 4]).bytes() {
+                    name.push_str(&format!("%{:02X}", byte));
+                }
+            }
+        }
+    }
+    if name.is_empty() {
+        name.push('_'); // Never the encoding of a name
+    }
+    Some(data_dir()?.join(subdir).join(format!("{}.txt", name)))
+}
+
+fn load() -> Config {
+    let path = xdg_dir("XDG_CONFIG_HOME", ".config").map(|dir| dir.join("config.toml"));
+    match path.and_then(|path| fs::read_to_string(path).ok()) {
+        Some(text) => parse(&text),
+        None => Config::default(),
+    }
+}
+
+fn parse(text: &str) -> Config {
+    let mut config = Config::default();
+    for line in text.lines().map(str::trim) {
+        if line.starts_with('#') {
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let value = value.trim().trim_matches('"');
+        match key.trim() {
+            "editing" => {
+                config.editing = match value {
+                    "emacs" | "readline" => EditingProfile::Emacs,
+                    _ => EditingProfile::Vim,
+                }
+            }
+            "auto_pairs" => config.auto_pairs = value == "true",
+            "wrap" => config.wrap = value != "false",
+            "line_numbers" => {
+                config.line_numbers = match value {
+                    "absolute" | "true" => LineNumbers::Absolute,
+                    "relative" => LineNumbers::Relative,
+                    _ => LineNumbers::Off,
+                }
+            }
+            "token_warning" => config.token_warning = value.parse().ok(),
+            "chars_per_token" => {
+                config.chars_per_token = value.parse::<f32>().ok().filter(|ratio| *ratio > 0.0)
+            }
+            "max_fps" => {
+                if let Ok(fps) = value.parse::<u32>()
+                    && fps > 0
+                {
+                    config.max_fps = fps;
+                }
+            }
+            "input_height" => {
+                if let Ok(fraction) = value.parse::<f32>() {
+                    config.input_height = fraction.clamp(0.0, 1.0);
+                }
+            }
+            _ => (),
+        }
+    }
+    config
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn settings() {
+        let config = parse(
+            "# halfmoon\n\
+             editing = \"emacs\"\n\
+             auto_pairs = true\n\
+             wrap = false\n\
+             line_numbers = relative\n\
+             token_warning = 500\n\
+             chars_per_token = 3.5\n\
+             max_fps = 60\n\
+             input_height = 0.8\n",
+        );
+        assert!(config.editing == EditingProfile::Emacs);
+        assert!(config.auto_pairs);
+        assert!(!config.wrap);
+        assert!(config.line_numbers == LineNumbers::Relative);
+        assert_eq!(config.token_warning, Some(500));
+        assert_eq!(config.chars_per_token, Some(3.5));
+        assert_eq!(config.max_fps, 60);
+        assert_eq!(config.input_height, 0.8);
+    }
+
+    #[test]
+    fn invalid_values_keep_the_defaults() {
+        let config =
+            parse("max_fps = 0\ninput_height = tall\ntoken_warning = many\nunknown = 1\nwrap");
+        assert!(config.editing == EditingProfile::Vim);
+        assert!(config.wrap);
+        assert_eq!(config.max_fps, 30);
+        assert_eq!(config.input_height, 0.5);
+        assert_eq!(config.token_warning, None);
+    }
+
+    #[test]
+    fn input_height_is_clamped() {
+        assert_eq!(parse("input_height = 3").input_height, 1.0);
+        assert_eq!(parse("input_height = -1").input_height, 0.0);
+    }
+
+    #[test]
+    fn persona_files_have_safe_names() {
+        if let Some(path) = persona_file("drafts", "../Ann Lee") {
+            assert_eq!(path.file_name().unwrap(), "%2E%2E%2FAnn%20Lee.txt");
+        }
+        if let Some(path) = persona_file("drafts", "") {
+            assert_eq!(path.file_name().unwrap(), "_.txt");
+        }
+    }
+
+    #[test]
+    fn personas_have_files_of_their_own() {
+        let names = ["Ann Lee", "Ann_Lee", "Ann%20Lee", "Zoë", "", "_"];
+        let files: Vec<_> = names
+            .iter()
+            .map(|name| persona_file("drafts", name))
+            .collect();
+        for (i, file) in files.iter().enumerate() {
+            assert!(file.is_none() || !files[..i].contains(file));
+        }
+    }
+}
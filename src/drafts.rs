@@ -0,0 +1,146 @@
+// Unsent text of the input editor and of the message being edited, kept for each persona in
+// $XDG_DATA_HOME/halfmoon/drafts/ and edits/ so that it survives quitting or a crash.
+// The draft of an edit starts with the edited message on its own line, as the variants shown of the messages up to
+// it: the message at the same index on another branch is another message. The draft is kept until the message is
+// edited again, or until the message is not in the chat any more.
+
+use std::{fs, path::PathBuf};
+
+use crate::config;
+
+pub struct Drafts {
+    input_path: Option<PathBuf>,
+    edit_path: Option<PathBuf>,
+    input: String, // Texts as last written, to only write them again when they change
+    edit: Option<(Vec<usize>, String)>,
+}
+
+impl Drafts {
+    pub fn load(persona: &str) -> Self {
+        let input_path = config::persona_file("drafts", persona);
+        let edit_path = config::persona_file("edits", persona);
+        let input = read(&input_path).unwrap_or_default();
+        let edit = read(&edit_path).and_then(|text| {
+            let (message, text) = text.split_once('\n')?;
+            let message: Vec<usize> = message
+                .split_whitespace()
+                .map(str::parse)
+                .collect::<Result<_, _>>()
+                .ok()?;
+            Some((message, text.to_string())).filter(|(message, _)| !message.is_empty())
+        });
+        Self {
+            input_path,
+            edit_path,
+            input,
+            edit,
+        }
+    }
+
+    /// Text left in the input editor
+    pub fn input(&self) -> Option<&str> {
+        match self.input.is_empty() {
+            true => None,
+            false => Some(&self.input),
+        }
+    }
+
+    /// Text left in the editor of `message`, when it was the one being edited
+    pub fn edit(&self, message: &[usize]) -> Option<&str> {
+        self.edit
+            .as_ref()
+            .filter(|(edited, _)| edited == message)
+            .map(|(_, text)| text.as_str())
+    }
+
+    /// Message the draft of an edit was left for
+    pub fn edited(&self) -> Option<&[usize]> {
+        self.edit.as_ref().map(|(message, _)| message.as_slice())
+    }
+
+    /// Write the texts of the editors that changed since the last save, removing the drafts left empty. The draft of
+    /// an edit is kept when no message is being edited.
+    pub fn save(&mut self, input: &str, edit: Option<(&[usize], &str)>) {
+        let input = draft_text(input);
+        if input != self.input {
+            self.input = input.to_string();
+            write(
+                &self.input_path,
+                Some(input).filter(|text| !text.is_empty()),
+            );
+        }
+        if let Some((message, text)) = edit {
+            let text = draft_text(text);
+            self.write_edit(
+                Some((message.to_vec(), text.to_string())).filter(|_| !text.is_empty()),
+            );
+        }
+    }
+
+    /// Forget the draft of an edit, once the editor is left or the message is gone
+    pub fn drop_edit(&mut self) {
+        self.write_edit(None);
+    }
+
+    fn write_edit(&mut self, edit: Option<(Vec<usize>, String)>) {
+        if edit == self.edit {
+            return;
+        }
+        self.edit = edit;
+        let text = self.edit.as_ref().map(|(message, text)| {
+            let message: Vec<String> = message.iter().map(usize::to_string).collect();
+            format!("{}\n{}", message.join(" "), text)
+        });
+        write(&self.edit_path, text.as_deref());
+    }
+}
+
+/// Text of an editor without the line break ending its last line, empty when there is nothing but white space
+fn draft_text(text: &str) -> &str {
+    let text = text.strip_suffix('\n').unwrap_or(text);
+    match text.trim().is_empty() {
+        true => "",
+        false => text,
+    }
+}
+
+fn read(path: &Option<PathBuf>) -> Option<String> {
+    fs::read_to_string(path.as_ref()?).ok()
+}
+
+fn write(path: &Option<PathBuf>, text: Option<&str>) {
+    let Some(path) = path else {
+        return;
+    };
+    let _ = match text {
+        Some(text) => {
+            if let Some(dir) = path.parent() {
+                let _ = fs::create_dir_all(dir);
+            }
+            // Written beside the draft then renamed over it, so that a crash while writing keeps the previous one
+            let temp = path.with_extension("txt.tmp");
+            fs::write(&temp, text).and_then(|_| fs::rename(&temp, path))
+        }
+        None => fs::remove_file(path),
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    #[test]
+    fn drafts_are_replaced_whole() {
+        let dir = env::temp_dir().join(format!("halfmoon-drafts-{}", std::process::id()));
+        let path = Some(dir.join("Ann.txt"));
+        write(&path, Some("first"));
+        write(&path, Some("second"));
+        assert_eq!(read(&path).as_deref(), Some("second"));
+        assert!(!dir.join("Ann.txt.tmp").exists());
+        write(&path, None);
+        assert_eq!(read(&path), None);
+        let _ = fs::remove_dir(&dir);
+    }
+}
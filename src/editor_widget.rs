@@ -2,39 +2,90 @@ use std::fmt;
 
 use crossterm::event::Event;
 use ratatui::{
-    prelude::Widget,
     style::{Color, Modifier, Style},
+    text::{Line, Span},
     widgets::{Block, Borders, StatefulWidget},
 };
-use tui_textarea::{CursorMove, Input, Key, Scrolling, TextArea};
+use tui_textarea::{CursorMove, Input, Key, TextArea};
 
-#[derive(PartialEq, Default)]
+use self::completion::Completion;
+pub use self::counter::{Tokenizer, configured_tokenizer};
+use self::highlight::Highlight;
+pub use self::history::History;
+use self::history::Snapshot;
+use self::recall::Recall;
+use self::text_object::Pos;
+pub use self::view::Display;
+use self::view::{Selection, Viewport};
+use crate::{
+    config::{self, EditingProfile, LineNumbers},
+    registers,
+};
+
+mod completion;
+mod counter;
+mod ex;
+mod highlight;
+mod history;
+mod motion;
+mod recall;
+mod surround;
+mod text_object;
+mod view;
+
+#[derive(PartialEq, Default, Clone, Copy)]
 enum Mode {
     #[default]
     Normal,
     Insert,
+    Replace,
     Visual,
+    VisualBlock,
     Operator(char),
+    Surround(Option<char>), // Waiting for the delimiter to surround the selection with, or to replace this one with
+    Search(char),
+    Command,
+    HistorySearch,
+    Modeless, // Editing with readline key bindings, for the emacs profile
 }
 
 impl Mode {
-    fn block<'a>(&self) -> Block<'a> {
+    fn block<'a>(&self, pending: &str, recording: Option<char>) -> Block<'a> {
         let help = match self {
             Self::Normal => "type Esc to quit, type i to enter insert mode",
-            Self::Insert => "type Esc to back to normal mode",
+            Self::Insert | Self::Replace => "type Esc to back to normal mode",
             Self::Visual => "type y to yank, type d to delete, type Esc to back to normal mode",
-            Self::Operator(_) => "move cursor to apply operator",
+            Self::VisualBlock => {
+                "type I or A to insert on every line, type y, d or c to edit the block, type Esc to back to normal mode"
+            }
+            Self::Operator(_) => "move cursor or type a text object to apply operator",
+            Self::Surround(_) => "type the character to surround with, type Esc to cancel",
+            Self::Search(_) => "type Enter to search, type Esc to cancel",
+            Self::Command => "type Enter to run the command, type Esc to cancel",
+            Self::HistorySearch => {
+                "type to search the sent messages, type Ctrl-R for an older one, type Enter to accept, type Esc to cancel"
+            }
+            Self::Modeless => "type Enter to send, type Alt-Enter for a new line, type Esc to quit",
         };
-        let title = format!("{} MODE ({})", self, help);
+        let mut title = format!("{} MODE", self);
+        if !pending.is_empty() {
+            title.push_str(&format!(" [{}]", pending));
+        }
+        if let Some(register) = recording {
+            title.push_str(&format!(" recording @{}", register));
+        }
+        title.push_str(&format!(" ({})", help));
         Block::default().borders(Borders::ALL).title(title)
     }
 
     fn focused_cursor_style(&self) -> Style {
         let color = match self {
             Self::Normal => Color::Reset,
-            Self::Insert => Color::LightBlue,
-            Self::Visual => Color::LightYellow,
-            Self::Operator(_) => Color::LightGreen,
+            Self::Insert | Self::Modeless => Color::LightBlue,
+            Self::Replace => Color::LightRed,
+            Self::Visual | Self::VisualBlock => Color::LightYellow,
+            Self::Operator(_) | Self::Surround(_) => Color::LightGreen,
+            Self::Search(_) | Self::Command | Self::HistorySearch => Color::LightMagenta,
         };
         Style::default().fg(color).add_modifier(Modifier::REVERSED)
     }
@@ -49,16 +100,48 @@ impl fmt::Display for Mode {
         match self {
             Self::Normal => write!(f, "NORMAL"),
             Self::Insert => write!(f, "INSERT"),
+            Self::Replace => write!(f, "REPLACE"),
             Self::Visual => write!(f, "VISUAL"),
+            Self::VisualBlock => write!(f, "VISUAL BLOCK"),
             Self::Operator(c) => write!(f, "OPERATOR({})", c),
+            Self::Surround(_) => write!(f, "SURROUND"),
+            Self::Search(_) => write!(f, "SEARCH"),
+            Self::Command => write!(f, "COMMAND"),
+            Self::HistorySearch => write!(f, "HISTORY SEARCH"),
+            Self::Modeless => write!(f, "EDIT"),
         }
     }
 }
 
+/// Keys of a command, recorded to be repeated with .
+#[derive(Clone, Default)]
+struct Change {
+    count: Option<usize>,
+    keys: Vec<Input>,
+}
+
+/// Insertion started with I, A or c in visual block mode, repeated on every line of the block when it ends
+struct BlockInsert {
+    top: usize,
+    bottom: usize,
+    cells: (usize, usize), // Display columns of the block, inclusive, up to usize::MAX when it reaches the line ends
+    col: usize,            // Where the text is typed on the top line
+    append: bool,          // With A, lines shorter than the block are padded instead of skipped
+    changed: bool,         // With c, the lines ending where the block was cut get the text too
+    len: usize,            // Length of the top line before the insertion
+}
+
+/// Largest count, as in Vim
+const MAX_COUNT: usize = 999_999_999;
+
+/// Characters a put with a count may insert at most, beyond which it is refused instead of hanging
+const MAX_PUT_LEN: usize = 10_000_000;
+
 enum Transition {
     Nop,
     Mode(Mode),
     Pending(Input),
+    Count(usize),
     Ok,
     Quit,
 }
@@ -67,6 +150,35 @@ pub struct EditorState {
     editor: TextArea<'static>,
     mode: Mode,
     pending: Input, // Pending input to handle a sequence with two keys like gg
+    count: Option<usize>,
+    operator_count: Option<usize>, // Count typed before the operator, as in 2d3w
+    register: Option<char>,        // Register selected with "
+    last_find: Option<(char, char)>, // Last f, F, t or T search and its character, repeated by ; and ,
+    prompt: String, // Text typed on the prompt line of the search and command modes
+    prompt_origin: Mode, // Mode to go back to when the prompt is closed
+    visual_rows: Option<(usize, usize)>, // Lines selected when the command prompt was opened
+    last_search: String,
+    search_backward: bool, // Direction of the last search, followed by n and reversed by N
+    message: Option<String>, // Feedback shown at the bottom of the block until the next key
+    change: Option<Change>, // Command being typed, started in normal mode
+    change_before: Vec<String>, // Text before the command, to know if it changed something
+    last_change: Option<Change>,
+    recording: Option<(char, Vec<Input>)>, // Register and keys of the macro being recorded
+    last_macro: Option<char>,              // Register replayed by @@
+    macro_depth: usize, // Macros being replayed, to stop a macro calling itself forever
+    history: History,
+    undo_before: Option<Snapshot>, // Text before the command being typed, saved in the history if it changes it
+    replaced: Vec<Option<char>>,   // Characters overwritten in replace mode, put back by Backspace
+    block_anchor: Pos,             // Corner of the visual block opposite to the cursor
+    block_to_end: bool,            // The block reaches the end of every line, after $
+    block_insert: Option<BlockInsert>,
+    viewport: Viewport,
+    display: Display,
+    recall: Recall,
+    completions: Vec<String>, // Words completed by Tab and Ctrl-N in insert mode
+    completion: Option<Completion>,
+    highlight: Highlight,
+    tokenizer: Box<dyn Tokenizer>, // Estimates the tokens of the text shown in the border
     single_line: bool,
 }
 
@@ -84,32 +196,501 @@ pub enum EditorResult {
 
 impl EditorState {
     pub fn new(text: String, single_line: bool) -> Self {
-        let mode = Mode::default();
+        let mode = match config::get().editing {
+            EditingProfile::Vim => Mode::default(),
+            EditingProfile::Emacs => Mode::Modeless,
+        };
         let mut editor = TextArea::from(text.split('\n'));
-        editor.set_block(mode.block());
         editor.set_cursor_style(mode.focused_cursor_style());
-        Self {
+        editor.set_search_style(Style::default().bg(Color::Yellow).fg(Color::Black));
+        editor.set_max_histories(0); // Undo is handled by EditorState, one command at a time
+        let mut state = Self {
             editor,
             mode,
             pending: Default::default(),
+            count: None,
+            operator_count: None,
+            register: None,
+            last_find: None,
+            prompt: String::new(),
+            prompt_origin: Mode::Normal,
+            visual_rows: None,
+            last_search: String::new(),
+            search_backward: false,
+            message: None,
+            change: None,
+            change_before: Vec::new(),
+            last_change: None,
+            recording: None,
+            last_macro: None,
+            macro_depth: 0,
+            history: History::default(),
+            undo_before: None,
+            replaced: Vec::new(),
+            block_anchor: (0, 0),
+            block_to_end: false,
+            block_insert: None,
+            viewport: Viewport::default(),
+            display: match single_line {
+                true => Display {
+                    wrap: false, // The search bars scroll instead
+                    numbers: LineNumbers::Off,
+                },
+                false => Display {
+                    wrap: config::get().wrap,
+                    numbers: config::get().line_numbers,
+                },
+            },
+            recall: Recall::default(),
+            completions: Vec::new(),
+            completion: None,
+            highlight: Highlight::default(),
+            tokenizer: Box::new(counter::Heuristic),
             single_line,
-        }
+        };
+        state.refresh_block();
+        state
     }
 
     pub fn input(&mut self, event: Event) -> EditorResult {
-        match self.transition(event.into()) {
+        if let Event::Paste(text) = event {
+            self.insert_pasted(&text);
+            return EditorResult::None;
+        }
+        let input: Input = event.into();
+        let was_recording = self.recording.is_some();
+        let result = self.handle(input.clone());
+        // The keys starting and stopping the recording are not part of the macro
+        if let Some((_, keys)) = &mut self.recording
+            && was_recording
+        {
+            keys.push(input);
+        }
+        result
+    }
+
+    fn handle(&mut self, input: Input) -> EditorResult {
+        self.message = None;
+        let idle =
+            self.mode == Mode::Normal && self.pending.key == Key::Null && self.register.is_none();
+        if idle
+            && matches!(
+                input,
+                Input {
+                    key: Key::Char('.'),
+                    ctrl: false,
+                    alt: false,
+                    ..
+                }
+            )
+        {
+            self.repeat_change();
+            self.refresh_block();
+            return EditorResult::None;
+        }
+        if let (
+            Mode::Normal,
+            Input {
+                key: Key::Char('@'),
+                ..
+            },
+            Input {
+                key: Key::Char(register),
+                ctrl: false,
+                alt: false,
+                ..
+            },
+        ) = (self.mode, &self.pending, &input)
+        {
+            let register = *register;
+            let count = self.count();
+            self.pending = Input::default();
+            self.count = None;
+            self.change = None; // The replayed keys are recorded as changes of their own
+            self.run_macro(register, count);
+            self.refresh_block();
+            return EditorResult::None;
+        }
+        if idle && self.change.is_none() {
+            self.change = Some(Change::default());
+            self.change_before = self.editor.lines().to_vec();
+        }
+        if self.undo_before.is_none() {
+            self.undo_before = Some(self.snapshot());
+        }
+
+        let count = self.count;
+        let transition = self.transition(input.clone());
+        if let Some(change) = &mut self.change {
+            match transition {
+                Transition::Count(_) if change.keys.is_empty() => (), // The count is kept apart to be replaced by the count of .
+                _ if change.keys.is_empty() => {
+                    change.count = count;
+                    change.keys.push(input);
+                }
+                _ => change.keys.push(input),
+            }
+        }
+
+        let result = match transition {
             Transition::Mode(mode) if self.mode != mode => {
-                self.editor.set_block(mode.block());
                 self.editor.set_cursor_style(mode.focused_cursor_style());
+                if !matches!(mode, Mode::Operator(_)) {
+                    self.operator_count = None;
+                    self.register = None;
+                }
                 self.mode = mode;
                 self.pending = Input::default();
+                self.count = None;
+                EditorResult::None
+            }
+            Transition::Nop | Transition::Mode(_) => {
+                self.pending = Input::default();
+                self.count = None;
+                self.register = None;
+                EditorResult::None
+            }
+            Transition::Pending(input) => {
+                self.pending = input;
+                EditorResult::None
+            }
+            Transition::Count(count) => {
+                self.count = Some(count);
+                EditorResult::None
+            }
+            Transition::Ok => EditorResult::Ok,
+            Transition::Quit => EditorResult::Quit,
+        };
+        if self.mode == Mode::Normal
+            && self.pending.key == Key::Null
+            && self.count.is_none()
+            && self.register.is_none()
+        {
+            self.finish_change();
+            self.finish_undo_step();
+        }
+        self.refresh_block();
+        result
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            lines: self.editor.lines().to_vec(),
+            cursor: self.editor.cursor(),
+        }
+    }
+
+    /// Save the text as it was before the command that was just completed, if it modified it
+    fn finish_undo_step(&mut self) {
+        if let Some(before) = self.undo_before.take()
+            && before.lines != self.editor.lines()
+        {
+            self.history.push(before);
+        }
+    }
+
+    /// Undo (or redo) the last `count` commands
+    fn undo(&mut self, redo: bool) {
+        self.undo_before = None; // Undoing is not a change to undo
+        for _ in 0..self.count() {
+            let current = self.snapshot();
+            let snapshot = match redo {
+                true => self.history.redo(current),
+                false => self.history.undo(current),
+            };
+            let Some(snapshot) = snapshot else {
+                self.message = Some(match redo {
+                    true => "Already at newest change".to_string(),
+                    false => "Already at oldest change".to_string(),
+                });
+                return;
+            };
+            self.restore(snapshot);
+        }
+    }
+
+    /// Undo history of the editor, for a later editor of the same message to continue it
+    pub fn take_history(&mut self) -> History {
+        self.finish_undo_step();
+        let mut history = std::mem::take(&mut self.history);
+        history.leave(self.snapshot());
+        history
+    }
+
+    /// Continue the undo history of a previous editor of the same message
+    pub fn set_history(&mut self, mut history: History) {
+        history.resume(&self.snapshot());
+        self.history = history;
+    }
+
+    fn restore(&mut self, snapshot: Snapshot) {
+        self.set_text(&snapshot.lines.join("\n"));
+        self.jump(snapshot.cursor);
+    }
+
+    /// Replace the whole text, leaving the cursor at its end
+    fn set_text(&mut self, text: &str) {
+        let last = self.editor.lines().len() - 1;
+        let end = (last, self.editor.lines()[last].chars().count());
+        self.replace_range((0, 0), end, text);
+    }
+
+    /// Placeholder macros and names completed in insert mode
+    pub fn set_completions(&mut self, completions: Vec<String>) {
+        self.completions = completions;
+    }
+
+    /// Complete the word before the cursor (Tab, Ctrl-N), showing a popup when several candidates match.
+    /// Returns false when none does.
+    fn complete(&mut self) -> bool {
+        let (row, col) = self.editor.cursor();
+        let (start, prefix) = completion::word_before(&self.editor.lines()[row], col);
+        let matches = completion::matches(&self.completions, &prefix);
+        if matches.is_empty() {
+            return false;
+        }
+        self.replace_range((row, start), (row, col), &matches[0]);
+        if matches.len() > 1 {
+            self.completion = Some(Completion {
+                start: (row, start),
+                prefix,
+                matches,
+                selected: 0,
+            });
+        }
+        true
+    }
+
+    /// Keys of the completion popup: Tab and Ctrl-N insert the next match, Ctrl-P the previous one, Enter keeps the
+    /// match and Esc puts back the typed word. Any other key keeps the match and is handled as usual.
+    fn completion_input(&mut self, input: &Input) -> bool {
+        let Some(completion) = &mut self.completion else {
+            return false;
+        };
+        let (start, end) = (completion.start, completion.end());
+        let len = completion.matches.len();
+        let text = match input {
+            Input { key: Key::Tab, .. }
+            | Input {
+                key: Key::Char('n'),
+                ctrl: true,
+                ..
+            } => {
+                completion.selected = (completion.selected + 1) % len;
+                completion.matches[completion.selected].clone()
+            }
+            Input {
+                key: Key::Char('p'),
+                ctrl: true,
+                ..
+            } => {
+                completion.selected = (completion.selected + len - 1) % len;
+                completion.matches[completion.selected].clone()
+            }
+            Input { key: Key::Esc, .. } => {
+                let prefix = std::mem::take(&mut completion.prefix);
+                self.completion = None;
+                prefix
+            }
+            Input {
+                key: Key::Enter, ..
+            } => {
+                self.completion = None;
+                return true;
+            }
+            _ => {
+                self.completion = None;
+                return false;
+            }
+        };
+        self.replace_range(start, end, &text);
+        true
+    }
+
+    /// Messages that can be recalled with Ctrl-P, Ctrl-N and Ctrl-R, oldest first
+    pub fn set_recall(&mut self, entries: Vec<String>) {
+        self.recall = Recall::new(entries);
+    }
+
+    /// Estimate the tokens shown in the border with `tokenizer` instead of the heuristic
+    pub fn set_tokenizer(&mut self, tokenizer: Box<dyn Tokenizer>) {
+        self.tokenizer = tokenizer;
+        self.refresh_block();
+    }
+
+    /// Whether `input` recalls a sent message, and if it is an older one: Ctrl-P or Up on the first line, and Ctrl-N
+    /// or Down on the last line. On the other lines they move the cursor.
+    fn recall_direction(&self, input: &Input) -> Option<bool> {
+        if self.recall.is_empty() || self.pending.key != Key::Null {
+            return None;
+        }
+        let row = self.editor.cursor().0;
+        let older = match input {
+            Input {
+                key: Key::Char(c @ ('p' | 'n')),
+                ctrl: true,
+                alt: false,
+                ..
+            } => *c == 'p',
+            Input { key: Key::Up, .. } => true,
+            Input { key: Key::Down, .. } => false,
+            _ => return None,
+        };
+        match older {
+            true => (row == 0).then_some(true),
+            false => (row + 1 == self.editor.lines().len()).then_some(false),
+        }
+    }
+
+    /// Show the sent message before (or after) the one shown instead of the text
+    fn recall(&mut self, older: bool) {
+        let current = self.editor.lines().join("\n");
+        if let Some(text) = self.recall.step(&current, older) {
+            self.set_text(&text);
+        }
+    }
+
+    /// Show the newest sent message containing the prompt, older than the entry at `before`
+    fn search_history(&mut self, before: usize) {
+        if self.prompt.is_empty() {
+            return;
+        }
+        let current = self.editor.lines().join("\n");
+        let Some(entry) = self.recall.search(&self.prompt, before, &current) else {
+            return;
+        };
+        self.set_text(&entry);
+        // Put the cursor on the match
+        let before_match = &entry[..entry.find(&self.prompt).unwrap_or(0)];
+        let row = before_match.matches('\n').count();
+        let col = before_match
+            .rsplit('\n')
+            .next()
+            .unwrap_or("")
+            .chars()
+            .count();
+        self.jump((row, col));
+    }
+
+    /// Keep the command that was just completed if it modified the text, so that . can repeat it
+    fn finish_change(&mut self) {
+        let Some(change) = self.change.take() else {
+            return;
+        };
+        let undo = matches!(
+            change.keys.first(),
+            Some(Input {
+                key: Key::Char('u'),
+                ctrl: false,
+                ..
+            }) | Some(Input {
+                key: Key::Char('r'),
+                ctrl: true,
+                ..
+            })
+        );
+        if !undo && self.editor.lines() != self.change_before {
+            self.last_change = Some(change);
+        }
+        self.change_before.clear();
+    }
+
+    fn start_recording(&mut self, register: char) {
+        let keys = match register.is_ascii_uppercase() {
+            true => registers::get_macro(register.to_ascii_lowercase()).unwrap_or_default(), // Append to the register as in Vim
+            false => Vec::new(),
+        };
+        self.recording = Some((register.to_ascii_lowercase(), keys));
+    }
+
+    fn stop_recording(&mut self) {
+        if let Some((register, keys)) = self.recording.take() {
+            registers::set_macro(register, &keys);
+        }
+    }
+
+    fn run_macro(&mut self, register: char, count: usize) {
+        const MAX_DEPTH: usize = 100;
+
+        let register = match register {
+            '@' => match self.last_macro {
+                Some(register) => register,
+                None => {
+                    self.message = Some("No previously used register".to_string());
+                    return;
+                }
+            },
+            register => register,
+        };
+        let Some(keys) = registers::get_macro(register) else {
+            self.message = Some(format!("Register {} is empty", register));
+            return;
+        };
+        if self.macro_depth >= MAX_DEPTH {
+            self.message = Some("Macro recursion is too deep".to_string());
+            return;
+        }
+        self.last_macro = Some(register);
+        self.macro_depth += 1;
+        for _ in 0..count {
+            let before = (self.snapshot(), self.mode);
+            for input in keys.iter().cloned() {
+                self.handle(input);
+            }
+            // A round changing nothing would change nothing again
+            if (self.snapshot(), self.mode) == before {
+                break;
+            }
+        }
+        self.macro_depth -= 1;
+    }
+
+    /// Replay the last change, with the count typed before . if any
+    fn repeat_change(&mut self) {
+        let Some(change) = self.last_change.clone() else {
+            return;
+        };
+        if let Some(count) = self.count.take().or(change.count) {
+            for digit in count.to_string().chars() {
+                self.handle(Input {
+                    key: Key::Char(digit),
+                    ..Default::default()
+                });
             }
-            Transition::Nop | Transition::Mode(_) => (),
-            Transition::Pending(input) => self.pending = input,
-            Transition::Ok => return EditorResult::Ok,
-            Transition::Quit => return EditorResult::Quit,
         }
-        EditorResult::None
+        for input in change.keys {
+            self.handle(input);
+        }
+    }
+
+    /// Feedback shown at the bottom of the block until the next key
+    pub fn set_message(&mut self, message: &str) {
+        self.message = Some(message.to_string());
+        self.refresh_block();
+    }
+
+    /// Number of lines the text takes on screen in an editor `width` cells wide, for the input area to grow with it
+    pub fn display_rows(&self, width: u16) -> usize {
+        let lines = self.editor.lines();
+        if !self.display.wrap {
+            return lines.len();
+        }
+        let inner = (width as usize).saturating_sub(2); // Without the borders
+        let width = inner - view::gutter_width(self.display.numbers, lines.len(), inner);
+        let tab_len = self.tab_len();
+        lines
+            .iter()
+            .map(|line| view::wrap_line(line, width, tab_len).len())
+            .sum()
+    }
+
+    /// Whether lines wrap and how they are numbered, to carry them over to another editor
+    pub fn display(&self) -> Display {
+        self.display
+    }
+
+    pub fn set_display(&mut self, display: Display) {
+        self.display = display;
     }
 
     pub fn text(&self) -> String {
@@ -122,43 +703,1634 @@ impl EditorState {
     }
 
     pub fn insert_mode(&mut self) {
-        self.mode = Mode::Insert
+        if self.mode == Mode::Modeless {
+            return;
+        }
+        self.mode = Mode::Insert;
+        self.refresh_block();
+    }
+
+    fn refresh_block(&mut self) {
+        let recording = self.recording.as_ref().map(|(register, _)| *register);
+        let mut block = self.mode.block(&self.pending_keys(), recording);
+        let prompt = match self.mode {
+            Mode::Search(c) => Some(c.to_string()),
+            Mode::Command => Some(":".to_string()),
+            Mode::HistorySearch
+                if !self.prompt.is_empty()
+                    && !self.editor.lines().join("\n").contains(&self.prompt) =>
+            {
+                Some("failing bck-i-search: ".to_string())
+            }
+            Mode::HistorySearch => Some("bck-i-search: ".to_string()),
+            _ => None,
+        };
+        if let Some(prefix) = prompt {
+            let cursor = Style::default().add_modifier(Modifier::REVERSED);
+            block = block.title_bottom(Line::from(vec![
+                Span::raw(format!("{}{}", prefix, self.prompt)),
+                Span::styled(" ", cursor),
+            ]));
+        } else if let Some(message) = &self.message {
+            block = block.title_bottom(message.clone());
+        }
+        if !self.single_line
+            && let Some(&((row, _), marker)) = self.highlight.update(self.editor.lines()).1.first()
+        {
+            let warning = format!("unclosed {} on line {}", marker, row + 1);
+            block = block.title_bottom(Line::styled(warning, Color::LightRed).right_aligned());
+        }
+        if !self.single_line {
+            let count = counter::count(&self.editor.lines().join("\n"), self.tokenizer.as_ref());
+            let size = Line::raw(format!(
+                "{} chars, {} words, ~{} tokens",
+                count.chars, count.words, count.tokens
+            ))
+            .right_aligned();
+            block = match config::get().token_warning {
+                Some(warning) if count.tokens > warning => block
+                    .title(size.style(Color::LightRed))
+                    .border_style(Style::default().fg(Color::LightRed)),
+                _ => block.title(size),
+            };
+        }
+        self.editor.set_block(block);
+    }
+
+    /// Keys typed so far for a command that is not complete yet, shown in the block title
+    fn pending_keys(&self) -> String {
+        let mut keys = String::new();
+        if let Some(register) = self.register {
+            keys.push('"');
+            keys.push(register);
+        }
+        if let Some(count) = self.operator_count {
+            keys.push_str(&count.to_string());
+        }
+        if let Mode::Operator(op) = self.mode {
+            match op {
+                'u' | 'U' | '~' => keys.push('g'),
+                's' => keys.push('y'),
+                _ => (),
+            }
+            keys.push(op);
+        }
+        if let Some(count) = self.count {
+            keys.push_str(&count.to_string());
+        }
+        if let Key::Char(c) = self.pending.key {
+            keys.push(c);
+        }
+        keys
+    }
+
+    /// Number of times the current command is repeated. Counts typed before and after an operator multiply
+    fn count(&self) -> usize {
+        let count = self.operator_count.unwrap_or(1);
+        count.saturating_mul(self.count.unwrap_or(1)).min(MAX_COUNT)
+    }
+
+    /// Move the cursor to `pos`. A jump of the text area only reaches the first 65536 lines and columns, the rest
+    /// of the way is walked.
+    fn jump(&mut self, (row, col): Pos) {
+        let clamp = |n: usize| n.min(u16::MAX as usize) as u16;
+        self.editor
+            .move_cursor(CursorMove::Jump(clamp(row), clamp(col)));
+        let row = row.min(self.editor.lines().len() - 1);
+        for _ in self.editor.cursor().0..row {
+            self.editor.move_cursor(CursorMove::Down);
+        }
+        let len = self.editor.lines()[row].chars().count();
+        if self.editor.cursor().1 < col.min(len) {
+            self.editor.move_cursor(CursorMove::End);
+            for _ in col..len {
+                self.editor.move_cursor(CursorMove::Back);
+            }
+        }
+    }
+
+    fn move_cursor(&mut self, m: CursorMove) {
+        self.repeat_move(self.count(), |state| {
+            state.editor.move_cursor(m);
+            true
+        });
+    }
+
+    /// Move the cursor down `count - 1` lines, used by commands working up to the end of a line like $ or D
+    fn move_lines_down(&mut self) {
+        self.repeat_move(self.count() - 1, |state| {
+            state.editor.move_cursor(CursorMove::Down);
+            true
+        });
+    }
+
+    /// Repeat the move made by `step` `count` times, returning false as soon as it fails. Stops early once the cursor
+    /// does not move any more, or skips the rounds when it comes back to where it started, so that a huge count
+    /// does not hang.
+    fn repeat_move(&mut self, count: usize, mut step: impl FnMut(&mut Self) -> bool) -> bool {
+        let start = self.editor.cursor();
+        for done in 1..=count {
+            let before = self.editor.cursor();
+            if !step(self) {
+                return false;
+            }
+            let cursor = self.editor.cursor();
+            if cursor == before {
+                break;
+            }
+            if cursor == start {
+                // Going round in `done` moves: only the last partial round changes where the cursor ends
+                for _ in 0..(count - done) % done {
+                    if !step(self) {
+                        return false;
+                    }
+                }
+                break;
+            }
+        }
+        true
+    }
+
+    /// Jump to the line given by the count, or to `default` when no count was typed (gg and G)
+    fn jump_to_line(&mut self, default: CursorMove) {
+        match self.count {
+            Some(_) => self.jump((self.count() - 1, 0)),
+            None => self.editor.move_cursor(default),
+        }
+    }
+
+    /// Delete from the cursor to the end of the line, and to the end of `count - 1` more lines (D and C)
+    fn delete_to_end(&mut self) {
+        self.editor.cancel_selection();
+        self.editor.start_selection();
+        self.move_lines_down();
+        self.editor.move_cursor(CursorMove::End);
+        self.cut();
+    }
+
+    /// Copy the selection to the selected register
+    fn copy(&mut self) {
+        if let Some((start, end)) = self.editor.selection_range()
+            && start != end
+        {
+            self.editor.copy();
+            self.store_yank();
+            self.jump(start); // Like Vim, leave the cursor at the start of the yanked text
+        }
+    }
+
+    /// Cut the selection to the selected register
+    fn cut(&mut self) {
+        if self.editor.cut() {
+            self.store_yank();
+        }
+    }
+
+    fn store_yank(&mut self) {
+        let register = self.register.unwrap_or('"');
+        if registers::set(register, self.editor.yank_text()).is_err() {
+            self.message = Some("Clipboard error".to_string());
+        }
+    }
+
+    fn paste(&mut self) {
+        let register = self.register.unwrap_or('"');
+        let Some(text) = registers::get(register) else {
+            self.message = Some(format!("Register {} is empty", register));
+            return;
+        };
+        match text.chars().count().checked_mul(self.count()) {
+            Some(len) if len <= MAX_PUT_LEN => {
+                self.editor.set_yank_text(text.repeat(self.count())); // Put at once rather than count times
+                self.editor.paste();
+            }
+            _ => self.message = Some("Resulting text too long".to_string()),
+        }
+    }
+
+    /// Insert text pasted in the terminal at once, as a single change that undo reverts.
+    /// In visual mode it replaces the selection, and in a prompt it is typed in the prompt.
+    fn insert_pasted(&mut self, text: &str) {
+        let text = text.replace("\r\n", "\n").replace('\r', "\n");
+        let text = match self.single_line {
+            true => text.replace('\n', " "),
+            false => text,
+        };
+        self.message = None;
+        self.pending = Input::default();
+        self.count = None;
+        if matches!(
+            self.mode,
+            Mode::Search(_) | Mode::Command | Mode::HistorySearch
+        ) {
+            self.prompt.push_str(&text.replace('\n', " "));
+            self.incremental_search();
+            self.refresh_block();
+            return;
+        }
+
+        self.finish_undo_step(); // What was typed before the paste is undone separately
+        self.undo_before = Some(self.snapshot());
+        if self.mode == Mode::Visual {
+            self.editor.move_cursor(CursorMove::Forward); // Vim's text selection is inclusive
+        }
+        match (self.mode, self.editor.selection_range()) {
+            (Mode::Visual, Some((start, end))) => self.replace_range(start, end, &text),
+            _ => {
+                self.editor.cancel_selection();
+                self.editor.insert_str(&text);
+            }
+        }
+        self.finish_undo_step();
+        if matches!(
+            self.mode,
+            Mode::Visual | Mode::VisualBlock | Mode::Operator(_) | Mode::Surround(_)
+        ) {
+            self.editor
+                .set_cursor_style(Mode::Normal.focused_cursor_style());
+            self.mode = Mode::Normal;
+        }
+        self.refresh_block();
+    }
+
+    /// Replace the text between `start` and `end` (exclusive), leaving the cursor after the new text
+    fn replace_range(&mut self, start: Pos, end: Pos, text: &str) {
+        self.editor.cancel_selection();
+        self.jump(start);
+        self.editor.start_selection();
+        self.jump(end);
+        self.editor.insert_str(text);
+    }
+
+    /// Change the case of the text between `start` and `end` (exclusive): u lowers it, U raises it and ~ toggles it
+    fn change_case(&mut self, start: Pos, end: Pos, op: char) {
+        let text = slice(self.editor.lines(), start, end);
+        let text = match op {
+            'u' => text.to_lowercase(),
+            'U' => text.to_uppercase(),
+            _ => text
+                .chars()
+                .map(|c| match c.is_uppercase() {
+                    true => c.to_lowercase().to_string(),
+                    false => c.to_uppercase().to_string(),
+                })
+                .collect(),
+        };
+        self.replace_range(start, end, &text);
+    }
+
+    /// Replace `count` characters from the cursor (r), or every selected character in visual mode
+    fn replace_chars(&mut self, c: char) -> bool {
+        let (start, end) = match self.editor.selection_range() {
+            Some(range) => range,
+            None => {
+                let (row, col) = self.editor.cursor();
+                let len = self.editor.lines()[row].chars().count();
+                if col + self.count() > len {
+                    return false;
+                }
+                ((row, col), (row, col + self.count()))
+            }
+        };
+        let text: String = slice(self.editor.lines(), start, end)
+            .chars()
+            .map(|old| if old == '\n' { old } else { c })
+            .collect();
+        self.replace_range(start, end, &text);
+        match self.mode {
+            Mode::Visual => self.jump(start),
+            _ => self.editor.move_cursor(CursorMove::Back), // Stay on the last replaced character
+        }
+        true
+    }
+
+    /// Join the lines from `first` to `last` (J), returning false when there is no line to join
+    fn join_lines(&mut self, first: usize, last: usize) -> bool {
+        let lines = self.editor.lines();
+        let last = last.min(lines.len() - 1);
+        if first >= last {
+            return false;
+        }
+        let mut joined = lines[first].clone();
+        let mut col = 0;
+        for line in &lines[first + 1..=last] {
+            let line = line.trim_start();
+            col = joined.chars().count();
+            // Like Vim, separate the lines with a single space unless there is already one
+            if !joined.is_empty()
+                && !line.is_empty()
+                && !joined.ends_with(char::is_whitespace)
+                && !line.starts_with(')')
+            {
+                joined.push(' ');
+            }
+            joined.push_str(line);
+        }
+        let end = (last, lines[last].chars().count());
+        self.replace_range((first, 0), end, &joined);
+        self.jump((first, col));
+        true
+    }
+
+    /// Select whole lines for an operator typed twice (yy, dd, cc, guu, ...). This is not strictly the same behavior as Vim
+    fn select_lines(&mut self) {
+        self.editor.move_cursor(CursorMove::Head);
+        self.editor.start_selection();
+        for _ in 0..self.count() {
+            let cursor = self.editor.cursor();
+            self.editor.move_cursor(CursorMove::Down);
+            if cursor == self.editor.cursor() {
+                self.editor.move_cursor(CursorMove::End); // At the last line, move to end of the line instead
+                break;
+            }
+        }
+    }
+
+    fn tab_len(&self) -> usize {
+        (self.editor.tab_length() as usize).max(1)
+    }
+
+    /// Like Vim, make the visual block reach the end of every line with $, until the cursor moves along a line
+    fn follow_line_ends(&mut self, input: &Input) {
+        match input {
+            Input {
+                key: Key::Char('$'),
+                ctrl: false,
+                ..
+            } => self.block_to_end = true,
+            Input {
+                key: Key::Char('0'),
+                ctrl: false,
+                ..
+            } if self.count.is_none() => self.block_to_end = false,
+            Input {
+                key: Key::Char(c),
+                ctrl: false,
+                ..
+            } if "hlwWbBeE^fFtT;,|nN*#%/?".contains(*c) => self.block_to_end = false,
+            Input {
+                key: Key::Left | Key::Right | Key::Home | Key::End,
+                ..
+            } => self.block_to_end = false,
+            _ => (),
+        }
+    }
+
+    /// Top left and bottom right corners of the visual block in display columns, inclusive. The block covers all the
+    /// cells of a tab or wide character at its corners, and the whole lines after $.
+    fn block_corners(&self) -> (Pos, Pos) {
+        let tab_len = self.tab_len();
+        let cells = |(row, col): Pos| {
+            let line = &self.editor.lines()[row];
+            let start = view::display_width(line.chars().take(col), tab_len);
+            let width = line
+                .chars()
+                .nth(col)
+                .map_or(1, |c| view::char_width(c, start, tab_len));
+            (start, start + width.max(1) - 1)
+        };
+        let (anchor, cursor) = (self.block_anchor, self.editor.cursor());
+        let (anchor_cells, cursor_cells) = (cells(anchor), cells(cursor));
+        let right = match self.block_to_end {
+            true => usize::MAX,
+            false => anchor_cells.1.max(cursor_cells.1),
+        };
+        (
+            (anchor.0.min(cursor.0), anchor_cells.0.min(cursor_cells.0)),
+            (anchor.0.max(cursor.0), right),
+        )
+    }
+
+    /// Characters of the line at `row` in the columns of the block, as a range of character indices
+    fn block_range(&self, row: usize, cells: (usize, usize)) -> (usize, usize) {
+        view::block_range(&self.editor.lines()[row], cells, self.tab_len())
+    }
+
+    /// Copy the visual block to the selected register, one line per line of the block
+    fn yank_block(&mut self) {
+        let ((top, left), (bottom, right)) = self.block_corners();
+        let text = (top..=bottom)
+            .map(|row| {
+                let (start, end) = self.block_range(row, (left, right));
+                self.editor.lines()[row]
+                    .chars()
+                    .skip(start)
+                    .take(end - start)
+                    .collect()
+            })
+            .collect::<Vec<String>>()
+            .join("\n");
+        self.editor.set_yank_text(text);
+        self.store_yank();
+    }
+
+    fn delete_block(&mut self) {
+        let ((top, left), (bottom, right)) = self.block_corners();
+        let col = self.block_range(top, (left, right)).0;
+        let lines: Vec<String> = (top..=bottom)
+            .map(|row| {
+                let range = self.block_range(row, (left, right));
+                self.editor.lines()[row]
+                    .chars()
+                    .enumerate()
+                    .filter(|(i, _)| !(range.0..range.1).contains(i))
+                    .map(|(_, c)| c)
+                    .collect()
+            })
+            .collect();
+        let end = (bottom, self.editor.lines()[bottom].chars().count());
+        self.replace_range((top, 0), end, &lines.join("\n"));
+        self.jump((top, col));
+    }
+
+    /// Pad the line at `row` with spaces to `width` display columns, when it is shorter
+    fn pad_line(&mut self, row: usize, width: usize) {
+        if width == usize::MAX {
+            return; // A block reaching the line ends appends at each line's own end
+        }
+        let line = &self.editor.lines()[row];
+        let (len, line_width) = (
+            line.chars().count(),
+            view::display_width(line.chars(), self.tab_len()),
+        );
+        if line_width < width {
+            self.replace_range((row, len), (row, len), &" ".repeat(width - line_width));
+        }
+    }
+
+    /// Start inserting before (I) or after (A) the block, or in its place once deleted (c), on its top line
+    fn start_block_insert(&mut self, ((top, left), (bottom, right)): (Pos, Pos), command: char) {
+        let append = command == 'A';
+        self.pad_line(
+            top,
+            if append {
+                right.saturating_add(1)
+            } else {
+                left
+            },
+        );
+        let (start, end) = self.block_range(top, (left, right));
+        let col = if append { end } else { start };
+        self.jump((top, col));
+        self.block_insert = Some(BlockInsert {
+            top,
+            bottom,
+            cells: (left, right),
+            col,
+            append,
+            changed: command == 'c',
+            len: self.editor.lines()[top].chars().count(),
+        });
+    }
+
+    /// Repeat the text typed on the top line of a block insert on the other lines of the block
+    fn finish_block_insert(&mut self) {
+        let Some(insert) = self.block_insert.take() else {
+            return;
+        };
+        let line = &self.editor.lines()[insert.top];
+        let added = line.chars().count().saturating_sub(insert.len);
+        if self.editor.cursor().0 != insert.top || added == 0 || insert.top == insert.bottom {
+            return; // Like Vim, give up when the insertion spans several lines
+        }
+        let text: Vec<char> = line.chars().skip(insert.col).take(added).collect();
+        let (left, right) = insert.cells;
+        let tab_len = self.tab_len();
+        let lines: Vec<String> = self.editor.lines()[insert.top + 1..=insert.bottom]
+            .iter()
+            .map(|line| {
+                let mut chars: Vec<char> = line.chars().collect();
+                let width = view::display_width(line.chars(), tab_len);
+                if !insert.append && (width < left || width == left && !insert.changed) {
+                    return line.clone(); // I skips the lines ending before the block
+                }
+                if insert.append && right != usize::MAX {
+                    chars.extend(std::iter::repeat_n(' ', (right + 1).saturating_sub(width)));
+                }
+                let line: String = chars.iter().collect();
+                let (start, end) = view::block_range(&line, (left, right), tab_len);
+                let col = if insert.append { end } else { start };
+                chars.splice(col..col, text.iter().copied());
+                chars.into_iter().collect()
+            })
+            .collect();
+        let end = (
+            insert.bottom,
+            self.editor.lines()[insert.bottom].chars().count(),
+        );
+        self.replace_range((insert.top + 1, 0), end, &lines.join("\n"));
+        self.jump((insert.top, insert.col));
+    }
+
+    /// Cut the text between the cursor and where `moves` take it, as the kill commands of readline do
+    fn kill(&mut self, moves: &[CursorMove]) {
+        self.editor.cancel_selection();
+        self.editor.start_selection();
+        for m in moves {
+            self.editor.move_cursor(*m);
+        }
+        self.cut();
+    }
+
+    /// Key bindings of the emacs profile, close to the ones of readline
+    fn modeless(&mut self, input: Input) -> Transition {
+        let typing = matches!(
+            input,
+            Input {
+                key: Key::Char(_),
+                ctrl: false,
+                alt: false,
+                ..
+            }
+        );
+        if !typing {
+            // Characters typed in a row are undone together, other commands one by one
+            self.finish_undo_step();
+            self.undo_before = Some(self.snapshot());
+        }
+        let transition = match input {
+            input if self.auto_pair(&input) => Transition::Nop,
+            Input { key: Key::Esc, .. }
+            | Input {
+                key: Key::Char('g'),
+                ctrl: true,
+                ..
+            } => Transition::Quit,
+            Input {
+                key: Key::Enter,
+                alt: true,
+                ..
+            } if !self.single_line => {
+                self.editor.insert_newline();
+                Transition::Nop
+            }
+            Input {
+                key: Key::Enter, ..
+            } => Transition::Ok,
+            Input {
+                key: Key::Char('a'),
+                ctrl: true,
+                ..
+            }
+            | Input { key: Key::Home, .. } => {
+                self.editor.move_cursor(CursorMove::Head);
+                Transition::Nop
+            }
+            Input {
+                key: Key::Char('e'),
+                ctrl: true,
+                ..
+            }
+            | Input { key: Key::End, .. } => {
+                self.editor.move_cursor(CursorMove::End);
+                Transition::Nop
+            }
+            Input {
+                key: Key::Char('b'),
+                ctrl: true,
+                ..
+            }
+            | Input { key: Key::Left, .. } => {
+                self.editor.move_cursor(CursorMove::Back);
+                Transition::Nop
+            }
+            Input {
+                key: Key::Char('f'),
+                ctrl: true,
+                ..
+            }
+            | Input {
+                key: Key::Right, ..
+            } => {
+                self.editor.move_cursor(CursorMove::Forward);
+                Transition::Nop
+            }
+            Input {
+                key: Key::Char('p'),
+                ctrl: true,
+                ..
+            }
+            | Input { key: Key::Up, .. } => {
+                self.editor.move_cursor(CursorMove::Up);
+                Transition::Nop
+            }
+            Input {
+                key: Key::Char('n'),
+                ctrl: true,
+                ..
+            }
+            | Input { key: Key::Down, .. } => {
+                self.editor.move_cursor(CursorMove::Down);
+                Transition::Nop
+            }
+            Input {
+                key: Key::Char('b'),
+                alt: true,
+                ..
+            } => {
+                self.editor.move_cursor(CursorMove::WordBack);
+                Transition::Nop
+            }
+            Input {
+                key: Key::Char('f'),
+                alt: true,
+                ..
+            } => {
+                // Readline moves to the end of the word, not to the start of the next one
+                self.editor.move_cursor(CursorMove::WordEnd);
+                self.editor.move_cursor(CursorMove::Forward);
+                Transition::Nop
+            }
+            Input {
+                key: Key::Char('d'),
+                ctrl: true,
+                ..
+            }
+            | Input {
+                key: Key::Delete, ..
+            } => {
+                self.editor.delete_next_char();
+                Transition::Nop
+            }
+            Input {
+                key: Key::Char('h'),
+                ctrl: true,
+                ..
+            }
+            | Input {
+                key: Key::Backspace,
+                alt: false,
+                ..
+            } => {
+                self.editor.delete_char();
+                Transition::Nop
+            }
+            Input {
+                key: Key::Char('k'),
+                ctrl: true,
+                ..
+            } => {
+                let (row, col) = self.editor.cursor();
+                match col < self.editor.lines()[row].chars().count() {
+                    true => self.kill(&[CursorMove::End]),
+                    false => self.kill(&[CursorMove::Forward]), // At the end of the line, kill the line break
+                }
+                Transition::Nop
+            }
+            Input {
+                key: Key::Char('u'),
+                ctrl: true,
+                ..
+            } => {
+                self.kill(&[CursorMove::Head]);
+                Transition::Nop
+            }
+            Input {
+                key: Key::Char('w'),
+                ctrl: true,
+                ..
+            }
+            | Input {
+                key: Key::Backspace,
+                alt: true,
+                ..
+            } => {
+                // Kill back to the previous white space
+                let (row, col) = self.editor.cursor();
+                let chars: Vec<char> = self.editor.lines()[row].chars().take(col).collect();
+                let mut start = col;
+                while start > 0 && chars[start - 1].is_whitespace() {
+                    start -= 1;
+                }
+                while start > 0 && !chars[start - 1].is_whitespace() {
+                    start -= 1;
+                }
+                self.kill(&vec![CursorMove::Back; col - start]);
+                Transition::Nop
+            }
+            Input {
+                key: Key::Char('d'),
+                alt: true,
+                ..
+            } => {
+                self.kill(&[CursorMove::WordEnd, CursorMove::Forward]);
+                Transition::Nop
+            }
+            Input {
+                key: Key::Char('y'),
+                ctrl: true,
+                ..
+            } => {
+                self.paste();
+                Transition::Nop
+            }
+            Input {
+                key: Key::Char('_' | '7' | 'z'),
+                ctrl: true,
+                ..
+            } => {
+                self.undo(false);
+                Transition::Nop
+            }
+            Input { key: Key::Tab, .. } => {
+                self.editor.insert_tab();
+                Transition::Nop
+            }
+            Input {
+                key: Key::Char(c),
+                ctrl: false,
+                alt: false,
+                ..
+            } => {
+                self.editor.insert_char(c);
+                Transition::Nop
+            }
+            _ => Transition::Nop,
+        };
+        if !typing {
+            self.finish_undo_step();
+        }
+        transition
+    }
+
+    /// Scroll the view by `rows`, moving the cursor along if `move_cursor` is set or if it would leave the view
+    fn scroll(&mut self, rows: isize, move_cursor: bool) {
+        let last = self.editor.lines().len() - 1;
+        let height = self.viewport.height.max(1);
+        self.viewport.row = self.viewport.row.saturating_add_signed(rows).min(last);
+        let (mut row, col) = self.editor.cursor();
+        if move_cursor {
+            row = row.saturating_add_signed(rows).min(last);
+        }
+        let row = row.clamp(self.viewport.row, self.viewport.row + height - 1);
+        self.jump((row.min(last), col));
+    }
+
+    /// Move the cursor to the display line below or above (gj, gk), at the same place on screen
+    fn move_display_line(&mut self, down: bool) {
+        if !self.display.wrap {
+            self.editor.move_cursor(match down {
+                true => CursorMove::Down,
+                false => CursorMove::Up,
+            });
+            return;
+        }
+        let lines = self.editor.lines();
+        let tab_len = self.tab_len();
+        let wrap = |row: usize| view::wrap_line(&lines[row], self.viewport.width, tab_len);
+        let (row, col) = self.editor.cursor();
+        let segments = wrap(row);
+        let i = view::segment_of(&segments, col);
+        let offset = col - segments[i].0;
+        let (row, (start, end), last) = match down {
+            true if i + 1 < segments.len() => (row, segments[i + 1], i + 2 == segments.len()),
+            true if row + 1 < lines.len() => {
+                let below = wrap(row + 1);
+                (row + 1, below[0], below.len() == 1)
+            }
+            false if i > 0 => (row, segments[i - 1], false),
+            false if row > 0 => (row - 1, *wrap(row - 1).last().unwrap_or(&(0, 0)), true),
+            _ => return,
+        };
+        // A display line that wraps ends before the space it wraps at
+        let max = match last {
+            true => end,
+            false => end.saturating_sub(1).max(start),
+        };
+        self.jump((row, (start + offset).min(max)));
+    }
+
+    fn render(&mut self, area: ratatui::prelude::Rect, buf: &mut ratatui::prelude::Buffer) {
+        let selection = match self.mode {
+            Mode::VisualBlock => {
+                let (top_left, bottom_right) = self.block_corners();
+                Some(Selection::Block(top_left, bottom_right))
+            }
+            _ => self
+                .editor
+                .selection_range()
+                .map(|(start, end)| Selection::Chars(start, end)),
+        };
+        // The search bars are not messages
+        let markup = match self.single_line {
+            true => None,
+            false => Some(self.highlight.update(self.editor.lines()).0),
+        };
+        let cursor = view::render(
+            &mut self.editor,
+            selection,
+            markup,
+            self.display,
+            &mut self.viewport,
+            area,
+            buf,
+        );
+        if let (Some(completion), Some(cursor)) = (&self.completion, cursor) {
+            completion::render(completion, cursor, buf);
+        }
+    }
+
+    /// Select the text object typed after i or a, returning false when there is none around the cursor
+    fn select_text_object(&mut self, object: char, around: bool) -> bool {
+        let cursor = self.editor.cursor();
+        let Some((start, end)) =
+            text_object::range(self.editor.lines(), cursor, object, around, self.count())
+        else {
+            return false;
+        };
+        self.editor.cancel_selection();
+        self.jump(start);
+        self.editor.start_selection();
+        self.jump(end);
+        if self.mode == Mode::Visual && start < end {
+            self.editor.move_cursor(CursorMove::Back); // Vim's text selection is inclusive
+        }
+        true
+    }
+
+    /// Move to `target` as an inclusive motion: an operator also applies to the character under the target
+    fn move_inclusive(&mut self, target: Pos) {
+        let cursor = self.editor.cursor();
+        let operator = matches!(self.mode, Mode::Operator(_));
+        if operator && target < cursor {
+            // Moving backward, the selection has to start after the character under the cursor
+            self.editor.cancel_selection();
+            self.editor.move_cursor(CursorMove::Forward);
+            self.editor.start_selection();
+        }
+        self.jump(target);
+        if operator && target >= cursor {
+            self.editor.move_cursor(CursorMove::Forward);
+        }
+    }
+
+    /// Run a character search (f, F, t, T), returning false when the character is not found
+    fn find_char(&mut self, find: (char, char), repeat: bool) -> bool {
+        let (row, col) = self.editor.cursor();
+        let line = &self.editor.lines()[row];
+        match motion::find_char(line, col, find, self.count(), repeat) {
+            Some(col) if matches!(find.0, 'f' | 't') => self.move_inclusive((row, col)),
+            Some(col) => self.jump((row, col)),
+            None => return false,
+        }
+        true
+    }
+
+    /// Use the last search pattern to highlight matches, returning false if it is not a valid regex
+    fn apply_search_pattern(&mut self) -> bool {
+        match self.editor.set_search_pattern(&self.last_search) {
+            Ok(_) => true,
+            Err(_) => {
+                self.message = Some(format!("Invalid pattern: {}", self.last_search));
+                false
+            }
+        }
+    }
+
+    /// Jump to the next match of the last search, in the opposite direction if `reverse` is set (N)
+    fn search_next(&mut self, reverse: bool) -> bool {
+        if self.last_search.is_empty() {
+            self.message = Some("No previous search".to_string());
+            return false;
+        }
+        if self.editor.search_pattern().is_none() && !self.apply_search_pattern() {
+            return false; // Highlighting was turned off by :noh
+        }
+        let found = self.repeat_move(self.count(), |state| {
+            match state.search_backward != reverse {
+                true => state.editor.search_back(false),
+                false => state.editor.search_forward(false),
+            }
+        });
+        if !found {
+            self.message = Some(format!("Pattern not found: {}", self.last_search));
+        }
+        found
+    }
+
+    /// Search the word under the cursor (* and #)
+    fn search_word(&mut self, backward: bool) -> bool {
+        let cursor = self.editor.cursor();
+        let Some(((row, start), (_, end))) =
+            text_object::range(self.editor.lines(), cursor, 'w', false, 1)
+        else {
+            return false;
+        };
+        let word: String = self.editor.lines()[row]
+            .chars()
+            .skip(start)
+            .take(end - start)
+            .collect();
+        self.last_search = match word.chars().any(|c| c.is_alphanumeric()) {
+            true => format!(r"\b{}\b", regex::escape(&word)),
+            false => regex::escape(&word),
+        };
+        self.search_backward = backward;
+        self.apply_search_pattern() && self.search_next(false)
+    }
+
+    /// Line editing on the prompt of the search and command modes
+    fn prompt(&mut self, input: Input) -> Transition {
+        match input {
+            Input {
+                key: Key::Enter, ..
+            } => match self.mode {
+                Mode::Search(direction) => self.submit_search(direction),
+                Mode::HistorySearch => {
+                    self.prompt.clear();
+                    Transition::Mode(self.prompt_origin)
+                }
+                _ => self.submit_command(),
+            },
+            Input {
+                key: Key::Char('r'),
+                ctrl: true,
+                ..
+            } if self.mode == Mode::HistorySearch => {
+                self.search_history(self.recall.position());
+                Transition::Nop
+            }
+            Input { key: Key::Esc, .. } => self.close_prompt(),
+            Input {
+                key: Key::Backspace,
+                ..
+            } if self.prompt.is_empty() => self.close_prompt(),
+            Input {
+                key: Key::Backspace,
+                ..
+            } => {
+                self.prompt.pop();
+                self.incremental_search();
+                Transition::Nop
+            }
+            Input {
+                key: Key::Char('u'),
+                ctrl: true,
+                ..
+            } => {
+                self.prompt.clear();
+                self.incremental_search();
+                Transition::Nop
+            }
+            Input {
+                key: Key::Char(c),
+                ctrl: false,
+                ..
+            } => {
+                self.prompt.push(c);
+                self.incremental_search();
+                Transition::Nop
+            }
+            _ => Transition::Nop,
+        }
+    }
+
+    fn submit_search(&mut self, direction: char) -> Transition {
+        if !self.prompt.is_empty() {
+            self.last_search = std::mem::take(&mut self.prompt);
+        }
+        self.search_backward = direction == '?';
+        let found = self.apply_search_pattern() && self.search_next(false);
+        match self.prompt_origin {
+            Mode::Operator(op) if found => self.apply_operator(op), // As in d/pattern
+            _ => self.close_prompt(),
+        }
+    }
+
+    /// Highlight the matches of the pattern being typed, keeping the previous highlight while it is not a valid regex
+    fn incremental_search(&mut self) {
+        if self.mode == Mode::HistorySearch {
+            self.search_history(self.recall.search_start());
+            return;
+        }
+        if !matches!(self.mode, Mode::Search(_)) {
+            return;
+        }
+        let pattern = match self.prompt.is_empty() {
+            true => &self.last_search,
+            false => &self.prompt,
+        };
+        let _ = self.editor.set_search_pattern(pattern);
+    }
+
+    fn close_prompt(&mut self) -> Transition {
+        self.prompt.clear();
+        if self.mode == Mode::HistorySearch
+            && let Some(text) = self.recall.cancel_search()
+        {
+            self.set_text(&text);
+        }
+        if matches!(self.mode, Mode::Search(_)) {
+            let _ = self.editor.set_search_pattern(&self.last_search);
+        }
+        match self.prompt_origin {
+            Mode::Visual | Mode::VisualBlock if self.mode == Mode::Command => {
+                self.editor.cancel_selection();
+                Transition::Mode(Mode::Normal)
+            }
+            Mode::Operator(_) => {
+                self.editor.cancel_selection();
+                Transition::Mode(Mode::Normal)
+            }
+            origin => Transition::Mode(origin),
+        }
+    }
+
+    fn submit_command(&mut self) -> Transition {
+        let command = std::mem::take(&mut self.prompt);
+        if command.trim().is_empty() {
+            return self.close_prompt();
+        }
+        let context = ex::Context {
+            cursor_row: self.editor.cursor().0,
+            last_row: self.editor.lines().len() - 1,
+            visual: self.visual_rows,
+        };
+        let command = ex::parse(&command, &context);
+        let transition = self.close_prompt();
+        match command {
+            Ok(ex::Command::Write) => {
+                self.mode = Mode::Normal;
+                Transition::Ok
+            }
+            Ok(ex::Command::Quit) => {
+                self.mode = Mode::Normal;
+                Transition::Quit
+            }
+            Ok(ex::Command::NoHighlight) => {
+                let _ = self.editor.set_search_pattern("");
+                transition
+            }
+            Ok(ex::Command::Line(row)) => {
+                self.editor.cancel_selection();
+                self.jump((row, 0));
+                transition
+            }
+            Ok(ex::Command::Set(setting)) => {
+                match setting {
+                    ex::Setting::Wrap(wrap) => self.display.wrap = wrap,
+                    ex::Setting::Number(true) => self.display.numbers = LineNumbers::Absolute,
+                    ex::Setting::RelativeNumber(true) => {
+                        self.display.numbers = LineNumbers::Relative
+                    }
+                    ex::Setting::Number(false) | ex::Setting::RelativeNumber(false) => {
+                        self.display.numbers = LineNumbers::Off
+                    }
+                }
+                transition
+            }
+            Ok(ex::Command::Substitute(rows, substitution)) => {
+                if let Err(message) = self.substitute(rows, substitution) {
+                    self.message = Some(message);
+                }
+                transition
+            }
+            Err(message) => {
+                self.message = Some(message);
+                transition
+            }
+        }
+    }
+
+    fn substitute(
+        &mut self,
+        (first, last): (usize, usize),
+        substitution: ex::Substitution,
+    ) -> Result<(), String> {
+        let regex = substitution.regex(&self.last_search)?;
+        let replacement = match self.single_line {
+            true => substitution.replacement().replace('\n', " "),
+            false => substitution.replacement(),
+        };
+
+        let mut substitutions = 0;
+        let mut changed_lines = 0;
+        let mut last_changed = 0; // Row of the last changed line, once replaced
+        let mut row = first;
+        let mut lines = Vec::new();
+        for line in &self.editor.lines()[first..=last] {
+            let matches = regex.find_iter(line).count();
+            let line = match (matches, substitution.global) {
+                (0, _) => line.clone(),
+                (_, true) => regex.replace_all(line, &replacement).into_owned(),
+                (_, false) => regex.replace(line, &replacement).into_owned(),
+            };
+            if matches > 0 {
+                substitutions += if substitution.global { matches } else { 1 };
+                changed_lines += 1;
+                last_changed = row;
+            }
+            row += line.matches('\n').count() + 1; // The replacement may split the line
+            lines.push(line);
+        }
+        if substitutions == 0 {
+            return Err(format!("Pattern not found: {}", regex.as_str()));
+        }
+
+        let end = (last, self.editor.lines()[last].chars().count());
+        self.replace_range((first, 0), end, &lines.join("\n"));
+        self.jump((last_changed, 0));
+
+        self.last_search = regex.as_str().to_string();
+        self.apply_search_pattern();
+        self.message = Some(format!(
+            "{} substitutions on {} lines",
+            substitutions, changed_lines
+        ));
+        Ok(())
+    }
+
+    /// Apply the operator to the text selected since it was typed
+    fn apply_operator(&mut self, op: char) -> Transition {
+        match op {
+            'y' => {
+                self.copy();
+                Transition::Mode(Mode::Normal)
+            }
+            'd' => {
+                self.cut();
+                Transition::Mode(Mode::Normal)
+            }
+            'c' => {
+                self.cut();
+                Transition::Mode(Mode::Insert)
+            }
+            'u' | 'U' | '~' => {
+                if let Some((start, end)) = self.editor.selection_range() {
+                    self.change_case(start, end, op);
+                    self.jump(start);
+                }
+                Transition::Mode(Mode::Normal)
+            }
+            's' => Transition::Mode(Mode::Surround(None)), // The delimiter is typed next
+            _ => Transition::Nop,
+        }
+    }
+
+    /// Surround the selection with the delimiters typed as `c` (ys, visual S)
+    fn surround(&mut self, c: char) {
+        let Some((start, end)) = self.editor.selection_range() else {
+            return;
+        };
+        let (open, close) = surround::pair(c);
+        self.replace_range(end, end, &close.to_string());
+        self.replace_range(start, start, &open.to_string());
+        self.jump(start);
+    }
+
+    /// Replace the delimiters typed as `from` around the cursor with those typed as `to` (cs), or delete them (ds).
+    /// Returns false when the cursor is not between such delimiters.
+    fn change_surround(&mut self, from: char, to: Option<char>) -> bool {
+        let Some((open_at, close_at)) =
+            surround::delimiters(self.editor.lines(), self.editor.cursor(), from)
+        else {
+            return false;
+        };
+        let (open, close) = match to.map(surround::pair) {
+            Some((open, close)) => (open.to_string(), close.to_string()),
+            None => (String::new(), String::new()),
+        };
+        // The closing delimiter first, to keep the position of the opening one
+        self.replace_range(close_at, (close_at.0, close_at.1 + 1), &close);
+        self.replace_range(open_at, (open_at.0, open_at.1 + 1), &open);
+        self.jump(open_at);
+        true
+    }
+
+    /// Close brackets, asterisks and quotes as they are typed when auto_pairs is set: the closing character is
+    /// inserted with the opening one, typed over, and deleted with it by Backspace. Returns whether `input` was handled.
+    fn auto_pair(&mut self, input: &Input) -> bool {
+        if !config::get().auto_pairs {
+            return false;
+        }
+        let (row, col) = self.editor.cursor();
+        let line: Vec<char> = self.editor.lines()[row].chars().collect();
+        let before = col.checked_sub(1).and_then(|i| line.get(i)).copied();
+        let after = line.get(col).copied();
+        match *input {
+            Input {
+                key: Key::Char(c),
+                ctrl: false,
+                alt: false,
+                ..
+            } => {
+                if after == Some(c) && matches!(c, ')' | ']' | '}' | '*' | '"') {
+                    self.editor.move_cursor(CursorMove::Forward);
+                    return true;
+                }
+                let close = match c {
+                    '(' => ')',
+                    '[' => ']',
+                    '{' => '}',
+                    // Not after a word, where it closes a pair or is an operator as in 2*3
+                    '*' | '"' if !before.is_some_and(char::is_alphanumeric) => c,
+                    _ => return false,
+                };
+                self.editor.insert_char(c);
+                self.editor.insert_char(close);
+                self.editor.move_cursor(CursorMove::Back);
+                true
+            }
+            Input {
+                key: Key::Backspace,
+                ..
+            } if matches!(
+                before.zip(after),
+                Some(('(', ')') | ('[', ']') | ('{', '}') | ('*', '*') | ('"', '"'))
+            ) =>
+            {
+                self.editor.delete_next_char();
+                self.editor.delete_char();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Abort the command after a motion failed
+    fn motion_failed(&mut self) -> Transition {
+        match self.mode {
+            Mode::Operator(_) => {
+                self.editor.cancel_selection();
+                Transition::Mode(Mode::Normal)
+            }
+            _ => Transition::Nop,
+        }
     }
 
     fn transition(&mut self, input: Input) -> Transition {
         if input.key == Key::Null {
             return Transition::Nop;
         }
+        if matches!(self.mode, Mode::Insert | Mode::Modeless) {
+            if self.completion_input(&input) {
+                return Transition::Nop;
+            }
+            if matches!(
+                input,
+                Input { key: Key::Tab, .. }
+                    | Input {
+                        key: Key::Char('n'),
+                        ctrl: true,
+                        alt: false,
+                        ..
+                    }
+            ) && self.complete()
+            {
+                return Transition::Nop;
+            }
+        }
+        if self.mode == Mode::VisualBlock && self.pending.key == Key::Null {
+            self.follow_line_ends(&input);
+        }
+        if matches!(self.mode, Mode::Normal | Mode::Insert | Mode::Modeless) {
+            if let Some(older) = self.recall_direction(&input) {
+                self.recall(older);
+                return Transition::Nop;
+            }
+            if let Input {
+                key: Key::Char('r'),
+                ctrl: true,
+                ..
+            } = input
+                && self.mode != Mode::Normal
+                && !self.recall.is_empty()
+            {
+                self.recall.start_search(&self.editor.lines().join("\n"));
+                self.prompt.clear();
+                self.prompt_origin = self.mode;
+                return Transition::Mode(Mode::HistorySearch);
+            }
+        }
 
         match self.mode {
-            Mode::Normal | Mode::Visual | Mode::Operator(_) => {
+            Mode::Normal | Mode::Visual | Mode::VisualBlock | Mode::Operator(_) => {
                 match input {
+                    // Esc, Enter or another special key after f, r, q, @, " or g only cancels the pending key
+                    Input { key, .. }
+                        if !matches!(key, Key::Char(_))
+                            && (self.pending.key != Key::Null || self.register.is_some()) =>
+                    {
+                        if let Mode::Operator(_) = self.mode {
+                            self.editor.cancel_selection();
+                            return Transition::Mode(Mode::Normal);
+                        }
+                        return Transition::Nop;
+                    }
+                    Input {
+                        key: Key::Char(target),
+                        ctrl: false,
+                        ..
+                    } if matches!(
+                        self.pending,
+                        Input {
+                            key: Key::Char('f' | 'F' | 't' | 'T'),
+                            ctrl: false,
+                            ..
+                        }
+                    ) =>
+                    {
+                        let Key::Char(kind) = self.pending.key else {
+                            unreachable!()
+                        };
+                        self.last_find = Some((kind, target));
+                        if !self.find_char((kind, target), false) {
+                            return self.motion_failed();
+                        }
+                    }
+                    Input {
+                        key: Key::Char(object),
+                        ctrl: false,
+                        ..
+                    } if self.mode != Mode::Normal
+                        && matches!(
+                            self.pending,
+                            Input {
+                                key: Key::Char('i' | 'a'),
+                                ctrl: false,
+                                ..
+                            }
+                        ) =>
+                    {
+                        let around = self.pending.key == Key::Char('a');
+                        if !self.select_text_object(object, around) {
+                            return self.motion_failed();
+                        }
+                    }
+                    Input {
+                        key: Key::Char(c),
+                        ctrl: false,
+                        ..
+                    } if matches!(self.mode, Mode::Normal | Mode::Visual)
+                        && matches!(
+                            self.pending,
+                            Input {
+                                key: Key::Char('r'),
+                                ctrl: false,
+                                ..
+                            }
+                        ) =>
+                    {
+                        if self.mode == Mode::Visual {
+                            self.editor.move_cursor(CursorMove::Forward); // Vim's text selection is inclusive
+                        }
+                        self.replace_chars(c);
+                        self.editor.cancel_selection();
+                        return Transition::Mode(Mode::Normal);
+                    }
+                    Input {
+                        key: Key::Char(c),
+                        ctrl: false,
+                        ..
+                    } if matches!(self.mode, Mode::Operator('d' | 'c'))
+                        && matches!(
+                            self.pending,
+                            Input {
+                                key: Key::Char('s'),
+                                ctrl: false,
+                                ..
+                            }
+                        ) =>
+                    {
+                        if self.mode == Mode::Operator('c') {
+                            return Transition::Mode(Mode::Surround(Some(c))); // The new delimiter is typed next
+                        }
+                        if !self.change_surround(c, None) {
+                            return self.motion_failed();
+                        }
+                        return Transition::Mode(Mode::Normal);
+                    }
+                    Input {
+                        key: Key::Char('s'),
+                        ctrl: false,
+                        ..
+                    } if self.mode == Mode::Operator('y') && self.pending.key == Key::Null => {
+                        return Transition::Mode(Mode::Operator('s'));
+                    }
+                    Input {
+                        key: Key::Char('s'),
+                        ctrl: false,
+                        ..
+                    } if self.mode == Mode::Operator('s') => {
+                        // yss surrounds the line, without its indentation
+                        self.editor.cancel_selection();
+                        self.editor.move_cursor(CursorMove::Head);
+                        let (row, _) = self.editor.cursor();
+                        let indent = self.editor.lines()[row]
+                            .chars()
+                            .take_while(|c| c.is_whitespace())
+                            .count();
+                        self.jump((row, indent));
+                        self.editor.start_selection();
+                        self.editor.move_cursor(CursorMove::End);
+                    }
+                    Input {
+                        key: Key::Char('S'),
+                        ctrl: false,
+                        ..
+                    } if self.mode == Mode::Visual => {
+                        self.editor.move_cursor(CursorMove::Forward); // Vim's text selection is inclusive
+                        return Transition::Mode(Mode::Surround(None));
+                    }
+                    Input {
+                        key: Key::Char(op @ ('u' | 'U' | '~')),
+                        ctrl: false,
+                        ..
+                    } if matches!(
+                        self.pending,
+                        Input {
+                            key: Key::Char('g'),
+                            ctrl: false,
+                            ..
+                        }
+                    ) =>
+                    {
+                        match self.mode {
+                            Mode::Normal => {
+                                self.operator_count = self.count;
+                                self.editor.start_selection();
+                                return Transition::Mode(Mode::Operator(op));
+                            }
+                            Mode::Visual => {
+                                self.editor.move_cursor(CursorMove::Forward); // Vim's text selection is inclusive
+                                return self.apply_operator(op);
+                            }
+                            mode if mode == Mode::Operator(op) => self.select_lines(), // gugu, gUgU
+                            _ => return self.motion_failed(),
+                        }
+                    }
+                    Input {
+                        key: Key::Char(register @ ('a'..='z' | 'A'..='Z')),
+                        ctrl: false,
+                        ..
+                    } if self.mode == Mode::Normal
+                        && matches!(
+                            self.pending,
+                            Input {
+                                key: Key::Char('q'),
+                                ctrl: false,
+                                ..
+                            }
+                        ) =>
+                    {
+                        self.start_recording(register);
+                        return Transition::Nop;
+                    }
+                    Input {
+                        key: Key::Char('q'),
+                        ctrl: false,
+                        ..
+                    } if self.mode == Mode::Normal => {
+                        if self.recording.is_none() {
+                            return Transition::Pending(input);
+                        }
+                        self.stop_recording();
+                        return Transition::Nop;
+                    }
+                    Input {
+                        key: Key::Char('@'),
+                        ctrl: false,
+                        ..
+                    } if self.mode == Mode::Normal => return Transition::Pending(input),
+                    Input {
+                        key: Key::Char(register),
+                        ctrl: false,
+                        ..
+                    } if matches!(
+                        self.pending,
+                        Input {
+                            key: Key::Char('"'),
+                            ctrl: false,
+                            ..
+                        }
+                    ) =>
+                    {
+                        if !registers::is_valid(register) {
+                            return self.motion_failed();
+                        }
+                        self.register = Some(register);
+                        return Transition::Pending(Input::default()); // Keep the count for the command using the register
+                    }
+                    Input {
+                        key: Key::Char('"'),
+                        ctrl: false,
+                        ..
+                    } if !matches!(self.mode, Mode::Operator(_)) => {
+                        return Transition::Pending(input);
+                    }
+                    Input { key: Key::Esc, .. }
+                    | Input {
+                        key: Key::Char('v'),
+                        ctrl: true,
+                        ..
+                    } if self.mode == Mode::VisualBlock => return Transition::Mode(Mode::Normal),
+                    Input {
+                        key: Key::Char('v'),
+                        ctrl: false,
+                        ..
+                    } if self.mode == Mode::VisualBlock => {
+                        // Switch to a charwise selection between the same corners
+                        let cursor = self.editor.cursor();
+                        self.jump(self.block_anchor);
+                        self.editor.start_selection();
+                        self.jump(cursor);
+                        return Transition::Mode(Mode::Visual);
+                    }
+                    Input {
+                        key: Key::Char('o'),
+                        ctrl: false,
+                        ..
+                    } if self.mode == Mode::VisualBlock => {
+                        let cursor = self.editor.cursor();
+                        self.jump(self.block_anchor);
+                        self.block_anchor = cursor;
+                        return Transition::Nop;
+                    }
+                    Input {
+                        key: Key::Char(c @ ('I' | 'A')),
+                        ctrl: false,
+                        ..
+                    } if self.mode == Mode::VisualBlock => {
+                        self.start_block_insert(self.block_corners(), c);
+                        return Transition::Mode(Mode::Insert);
+                    }
+                    Input {
+                        key: Key::Char(c @ ('y' | 'd' | 'x' | 'c')),
+                        ctrl: false,
+                        ..
+                    } if self.mode == Mode::VisualBlock => {
+                        let corners = self.block_corners();
+                        self.yank_block();
+                        if c == 'y' {
+                            let ((top, left), (_, right)) = corners;
+                            let col = self.block_range(top, (left, right)).0;
+                            self.jump((top, col));
+                            return Transition::Mode(Mode::Normal);
+                        }
+                        self.delete_block();
+                        if c == 'c' {
+                            self.start_block_insert(corners, 'c');
+                            return Transition::Mode(Mode::Insert);
+                        }
+                        return Transition::Mode(Mode::Normal);
+                    }
+                    input if self.mode == Mode::VisualBlock && !is_motion(&input) => {
+                        return Transition::Nop;
+                    }
+                    Input {
+                        key: Key::Char(c @ '0'..='9'),
+                        ctrl: false,
+                        alt: false,
+                        ..
+                    } if c != '0' || self.count.is_some() => {
+                        let digit = c.to_digit(10).unwrap_or_default() as usize;
+                        let count = self.count.unwrap_or_default();
+                        return Transition::Count((count * 10 + digit).min(MAX_COUNT));
+                    }
+                    Input {
+                        key: Key::Char(c @ ('j' | 'k')),
+                        ctrl: false,
+                        ..
+                    } if matches!(
+                        self.pending,
+                        Input {
+                            key: Key::Char('g'),
+                            ctrl: false,
+                            ..
+                        }
+                    ) =>
+                    {
+                        self.repeat_move(self.count(), |state| {
+                            state.move_display_line(c == 'j');
+                            true
+                        });
+                    }
                     Input {
                         key: Key::Char('h'),
                         ..
-                    } => self.editor.move_cursor(CursorMove::Back),
+                    } => self.move_cursor(CursorMove::Back),
                     Input {
                         key: Key::Char('j'),
                         ..
-                    } => self.editor.move_cursor(CursorMove::Down),
+                    } => self.move_cursor(CursorMove::Down),
                     Input {
                         key: Key::Char('k'),
                         ..
-                    } => self.editor.move_cursor(CursorMove::Up),
+                    } => self.move_cursor(CursorMove::Up),
                     Input {
                         key: Key::Char('l'),
                         ..
-                    } => self.editor.move_cursor(CursorMove::Forward),
+                    } => self.move_cursor(CursorMove::Forward),
                     Input {
                         key: Key::Char('w'),
                         ..
-                    } => self.editor.move_cursor(CursorMove::WordForward),
+                    } => self.move_cursor(CursorMove::WordForward),
                     Input {
                         key: Key::Char('e'),
                         ctrl: false,
                         ..
                     } => {
-                        self.editor.move_cursor(CursorMove::WordEnd);
+                        self.move_cursor(CursorMove::WordEnd);
                         if matches!(self.mode, Mode::Operator(_)) {
                             self.editor.move_cursor(CursorMove::Forward); // Include the text under the cursor
                         }
@@ -167,43 +2339,133 @@ impl EditorState {
                         key: Key::Char('b'),
                         ctrl: false,
                         ..
-                    } => self.editor.move_cursor(CursorMove::WordBack),
+                    } => self.move_cursor(CursorMove::WordBack),
+                    Input {
+                        key: Key::Char('f' | 'F' | 't' | 'T'),
+                        ctrl: false,
+                        ..
+                    } => return Transition::Pending(input),
+                    Input {
+                        key: Key::Char(c @ (';' | ',')),
+                        ctrl: false,
+                        ..
+                    } => {
+                        let Some((kind, target)) = self.last_find else {
+                            return self.motion_failed();
+                        };
+                        let kind = match c {
+                            ';' => kind,
+                            _ => motion::reverse_find(kind),
+                        };
+                        if !self.find_char((kind, target), true) {
+                            return self.motion_failed();
+                        }
+                    }
+                    Input {
+                        key: Key::Char(c @ ('/' | '?')),
+                        ctrl: false,
+                        ..
+                    } => {
+                        self.prompt.clear();
+                        self.prompt_origin = self.mode;
+                        return Transition::Mode(Mode::Search(c));
+                    }
+                    Input {
+                        key: Key::Char(':'),
+                        ctrl: false,
+                        ..
+                    } if !matches!(self.mode, Mode::Operator(_)) => {
+                        self.prompt.clear();
+                        self.prompt_origin = self.mode;
+                        self.visual_rows = None;
+                        let rows = match self.mode {
+                            Mode::VisualBlock => {
+                                let ((top, _), (bottom, _)) = self.block_corners();
+                                Some((top, bottom))
+                            }
+                            _ => self
+                                .editor
+                                .selection_range()
+                                .map(|((start, _), (end, _))| (start, end)),
+                        };
+                        if let Some(rows) = rows {
+                            self.visual_rows = Some(rows);
+                            self.prompt.push_str("'<,'>");
+                        }
+                        return Transition::Mode(Mode::Command);
+                    }
+                    Input {
+                        key: Key::Char(c @ ('n' | 'N')),
+                        ctrl: false,
+                        ..
+                    } => {
+                        if !self.search_next(c == 'N') {
+                            return self.motion_failed();
+                        }
+                    }
+                    Input {
+                        key: Key::Char(c @ ('*' | '#')),
+                        ctrl: false,
+                        ..
+                    } => {
+                        if !self.search_word(c == '#') {
+                            return self.motion_failed();
+                        }
+                    }
+                    Input {
+                        key: Key::Char('%'),
+                        ctrl: false,
+                        ..
+                    } => match motion::matching_pair(self.editor.lines(), self.editor.cursor()) {
+                        Some(target) => self.move_inclusive(target),
+                        None => return self.motion_failed(),
+                    },
                     Input {
-                        key: Key::Char('^'),
+                        key: Key::Char('^' | '0'),
                         ..
                     } => self.editor.move_cursor(CursorMove::Head),
                     Input {
                         key: Key::Char('$'),
                         ..
-                    } => self.editor.move_cursor(CursorMove::End),
+                    } => {
+                        self.move_lines_down();
+                        self.editor.move_cursor(CursorMove::End);
+                    }
                     Input {
                         key: Key::Char('D'),
                         ..
                     } => {
-                        self.editor.delete_line_by_end();
+                        self.delete_to_end();
                         return Transition::Mode(Mode::Normal);
                     }
                     Input {
                         key: Key::Char('C'),
                         ..
                     } => {
-                        self.editor.delete_line_by_end();
-                        self.editor.cancel_selection();
+                        self.delete_to_end();
                         return Transition::Mode(Mode::Insert);
                     }
                     Input {
                         key: Key::Char('p'),
                         ..
                     } => {
-                        self.editor.paste();
+                        self.paste();
                         return Transition::Mode(Mode::Normal);
                     }
+                    Input {
+                        key: Key::Char(op @ ('u' | 'U' | '~')),
+                        ctrl: false,
+                        ..
+                    } if self.mode == Mode::Visual => {
+                        self.editor.move_cursor(CursorMove::Forward); // Vim's text selection is inclusive
+                        return self.apply_operator(op);
+                    }
                     Input {
                         key: Key::Char('u'),
                         ctrl: false,
                         ..
-                    } => {
-                        self.editor.undo();
+                    } if self.mode == Mode::Normal => {
+                        self.undo(false);
                         return Transition::Mode(Mode::Normal);
                     }
                     Input {
@@ -211,16 +2473,73 @@ impl EditorState {
                         ctrl: true,
                         ..
                     } => {
-                        self.editor.redo();
+                        self.undo(true);
+                        return Transition::Mode(Mode::Normal);
+                    }
+                    Input {
+                        key: Key::Char('r'),
+                        ctrl: false,
+                        ..
+                    } if matches!(self.mode, Mode::Normal | Mode::Visual) => {
+                        return Transition::Pending(input);
+                    }
+                    Input {
+                        key: Key::Char('R'),
+                        ctrl: false,
+                        ..
+                    } if self.mode == Mode::Normal => {
+                        self.replaced.clear();
+                        return Transition::Mode(Mode::Replace);
+                    }
+                    Input {
+                        key: Key::Char('~'),
+                        ctrl: false,
+                        ..
+                    } if self.mode == Mode::Normal => {
+                        let (row, col) = self.editor.cursor();
+                        let len = self.editor.lines()[row].chars().count();
+                        let end = (col + self.count()).min(len);
+                        self.change_case((row, col), (row, end), '~');
+                        if end == len && len > 0 {
+                            self.editor.move_cursor(CursorMove::Back); // Stay on the last character of the line
+                        }
+                        return Transition::Mode(Mode::Normal);
+                    }
+                    Input {
+                        key: Key::Char('J'),
+                        ctrl: false,
+                        ..
+                    } if !matches!(self.mode, Mode::Operator(_)) => {
+                        let (first, last) = match self.editor.selection_range() {
+                            Some(((start, _), (end, _))) => (start, end.max(start + 1)),
+                            None => {
+                                let row = self.editor.cursor().0;
+                                (row, row + self.count().max(2) - 1)
+                            }
+                        };
+                        self.join_lines(first, last);
+                        self.editor.cancel_selection();
                         return Transition::Mode(Mode::Normal);
                     }
                     Input {
                         key: Key::Char('x'),
                         ..
                     } => {
-                        self.editor.delete_next_char();
+                        let (row, col) = self.editor.cursor();
+                        let len = self.editor.lines()[row].chars().count();
+                        if self
+                            .editor
+                            .delete_str(self.count().min(len.saturating_sub(col)))
+                        {
+                            self.store_yank();
+                        }
                         return Transition::Mode(Mode::Normal);
                     }
+                    Input {
+                        key: Key::Char('i' | 'a'),
+                        ctrl: false,
+                        ..
+                    } if self.mode != Mode::Normal => return Transition::Pending(input), // Text object
                     Input {
                         key: Key::Char('i'),
                         ..
@@ -270,7 +2589,14 @@ impl EditorState {
                         return Transition::Mode(Mode::Insert);
                     }
                     Input { key: Key::Esc, .. } if self.mode == Mode::Normal => {
-                        return Transition::Quit;
+                        return match self.count {
+                            Some(_) => Transition::Nop, // Esc first discards a pending count
+                            None => Transition::Quit,
+                        };
+                    }
+                    Input { key: Key::Esc, .. } if matches!(self.mode, Mode::Operator(_)) => {
+                        self.editor.cancel_selection();
+                        return Transition::Mode(Mode::Normal);
                     }
                     Input {
                         key: Key::Enter, ..
@@ -281,32 +2607,38 @@ impl EditorState {
                         key: Key::Char('e'),
                         ctrl: true,
                         ..
-                    } => self.editor.scroll((1, 0)),
+                    } => self.scroll(self.count() as isize, false),
                     Input {
                         key: Key::Char('y'),
                         ctrl: true,
                         ..
-                    } => self.editor.scroll((-1, 0)),
+                    } => self.scroll(-(self.count() as isize), false),
                     Input {
                         key: Key::Char('d'),
                         ctrl: true,
                         ..
-                    } => self.editor.scroll(Scrolling::HalfPageDown),
+                    } => self.scroll((self.viewport.height / 2).max(1) as isize, true),
                     Input {
                         key: Key::Char('u'),
                         ctrl: true,
                         ..
-                    } => self.editor.scroll(Scrolling::HalfPageUp),
+                    } => self.scroll(-((self.viewport.height / 2).max(1) as isize), true),
                     Input {
                         key: Key::Char('f'),
                         ctrl: true,
                         ..
-                    } => self.editor.scroll(Scrolling::PageDown),
+                    } => self.scroll(
+                        self.viewport.height.saturating_sub(2).max(1) as isize,
+                        false,
+                    ),
                     Input {
                         key: Key::Char('b'),
                         ctrl: true,
                         ..
-                    } => self.editor.scroll(Scrolling::PageUp),
+                    } => self.scroll(
+                        -(self.viewport.height.saturating_sub(2).max(1) as isize),
+                        false,
+                    ),
                     Input {
                         key: Key::Char('v'),
                         ctrl: false,
@@ -315,6 +2647,15 @@ impl EditorState {
                         self.editor.start_selection();
                         return Transition::Mode(Mode::Visual);
                     }
+                    Input {
+                        key: Key::Char('v'),
+                        ctrl: true,
+                        ..
+                    } if self.mode == Mode::Normal => {
+                        self.block_anchor = self.editor.cursor();
+                        self.block_to_end = false;
+                        return Transition::Mode(Mode::VisualBlock);
+                    }
                     Input {
                         key: Key::Char('V'),
                         ctrl: false,
@@ -347,32 +2688,24 @@ impl EditorState {
                         }
                     ) =>
                     {
-                        self.editor.move_cursor(CursorMove::Top)
+                        self.jump_to_line(CursorMove::Top)
                     }
                     Input {
                         key: Key::Char('G'),
                         ctrl: false,
                         ..
-                    } => self.editor.move_cursor(CursorMove::Bottom),
+                    } => self.jump_to_line(CursorMove::Bottom),
                     Input {
                         key: Key::Char(c),
                         ctrl: false,
                         ..
-                    } if self.mode == Mode::Operator(c) => {
-                        // Handle yy, dd, cc. (This is not strictly the same behavior as Vim)
-                        self.editor.move_cursor(CursorMove::Head);
-                        self.editor.start_selection();
-                        let cursor = self.editor.cursor();
-                        self.editor.move_cursor(CursorMove::Down);
-                        if cursor == self.editor.cursor() {
-                            self.editor.move_cursor(CursorMove::End); // At the last line, move to end of the line instead
-                        }
-                    }
+                    } if self.mode == Mode::Operator(c) => self.select_lines(), // yy, dd, cc, guu, gUU, g~~
                     Input {
                         key: Key::Char(op @ ('y' | 'd' | 'c')),
                         ctrl: false,
                         ..
                     } if self.mode == Mode::Normal => {
+                        self.operator_count = self.count;
                         self.editor.start_selection();
                         return Transition::Mode(Mode::Operator(op));
                     }
@@ -382,7 +2715,7 @@ impl EditorState {
                         ..
                     } if self.mode == Mode::Visual => {
                         self.editor.move_cursor(CursorMove::Forward); // Vim's text selection is inclusive
-                        self.editor.copy();
+                        self.copy();
                         return Transition::Mode(Mode::Normal);
                     }
                     Input {
@@ -391,7 +2724,7 @@ impl EditorState {
                         ..
                     } if self.mode == Mode::Visual => {
                         self.editor.move_cursor(CursorMove::Forward); // Vim's text selection is inclusive
-                        self.editor.cut();
+                        self.cut();
                         return Transition::Mode(Mode::Normal);
                     }
                     Input {
@@ -400,7 +2733,7 @@ impl EditorState {
                         ..
                     } if self.mode == Mode::Visual => {
                         self.editor.move_cursor(CursorMove::Forward); // Vim's text selection is inclusive
-                        self.editor.cut();
+                        self.cut();
                         return Transition::Mode(Mode::Insert);
                     }
                     input => return Transition::Pending(input),
@@ -408,18 +2741,7 @@ impl EditorState {
 
                 // Handle the pending operator
                 match self.mode {
-                    Mode::Operator('y') => {
-                        self.editor.copy();
-                        Transition::Mode(Mode::Normal)
-                    }
-                    Mode::Operator('d') => {
-                        self.editor.cut();
-                        Transition::Mode(Mode::Normal)
-                    }
-                    Mode::Operator('c') => {
-                        self.editor.cut();
-                        Transition::Mode(Mode::Insert)
-                    }
+                    Mode::Operator(op) => self.apply_operator(op),
                     _ => Transition::Nop,
                 }
             }
@@ -429,19 +2751,127 @@ impl EditorState {
                     key: Key::Char('c'),
                     ctrl: true,
                     ..
-                } => Transition::Mode(Mode::Normal),
+                } => {
+                    self.finish_block_insert();
+                    Transition::Mode(Mode::Normal)
+                }
                 Input {
                     key: Key::Enter, ..
                 } if self.single_line => Transition::Ok,
+                input if self.auto_pair(&input) => Transition::Mode(Mode::Insert),
                 input => {
                     self.editor.input(input); // Use default key mappings in insert mode
                     Transition::Mode(Mode::Insert)
                 }
             },
+            Mode::Replace => match input {
+                Input { key: Key::Esc, .. }
+                | Input {
+                    key: Key::Char('c'),
+                    ctrl: true,
+                    ..
+                } => Transition::Mode(Mode::Normal),
+                Input {
+                    key: Key::Enter, ..
+                } if self.single_line => Transition::Ok,
+                Input {
+                    key: Key::Char(c),
+                    ctrl: false,
+                    alt: false,
+                    ..
+                } => {
+                    let (row, col) = self.editor.cursor();
+                    let replaced = self.editor.lines()[row].chars().nth(col);
+                    if replaced.is_some() {
+                        self.editor.delete_next_char();
+                    }
+                    self.editor.insert_char(c);
+                    self.replaced.push(replaced);
+                    Transition::Mode(Mode::Replace)
+                }
+                Input {
+                    key: Key::Backspace,
+                    ..
+                } => {
+                    self.editor.move_cursor(CursorMove::Back);
+                    // Like Vim, put back the character that was replaced
+                    if let Some(replaced) = self.replaced.pop() {
+                        self.editor.delete_next_char();
+                        if let Some(c) = replaced {
+                            self.editor.insert_char(c);
+                            self.editor.move_cursor(CursorMove::Back);
+                        }
+                    }
+                    Transition::Mode(Mode::Replace)
+                }
+                input => {
+                    self.replaced.clear(); // The cursor may have moved elsewhere
+                    self.editor.input(input);
+                    Transition::Mode(Mode::Replace)
+                }
+            },
+            Mode::Surround(from) => {
+                if let Input {
+                    key: Key::Char(c),
+                    ctrl: false,
+                    alt: false,
+                    ..
+                } = input
+                {
+                    match from {
+                        Some(from) if !self.change_surround(from, Some(c)) => {
+                            self.message = Some(format!("No {} around the cursor", from));
+                        }
+                        Some(_) => (),
+                        None => self.surround(c),
+                    }
+                }
+                self.editor.cancel_selection();
+                Transition::Mode(Mode::Normal)
+            }
+            Mode::Search(_) | Mode::Command | Mode::HistorySearch => self.prompt(input),
+            Mode::Modeless => self.modeless(input),
         }
     }
 }
 
+/// Keys moving the cursor, the only ones allowed in visual block mode besides the block commands
+fn is_motion(input: &Input) -> bool {
+    match input {
+        Input {
+            key: Key::Char(c),
+            ctrl: false,
+            alt: false,
+            ..
+        } => "hjklwebfFtT;,/?nN*#%^$gG0123456789\":".contains(*c),
+        Input {
+            key: Key::Char(c),
+            ctrl: true,
+            ..
+        } => "eyudfb".contains(*c),
+        _ => false,
+    }
+}
+
+/// Text between `start` and `end` (exclusive)
+fn slice(lines: &[String], start: Pos, end: Pos) -> String {
+    lines
+        .iter()
+        .enumerate()
+        .skip(start.0)
+        .take(end.0 + 1 - start.0)
+        .map(|(row, line)| {
+            let from = if row == start.0 { start.1 } else { 0 };
+            let to = if row == end.0 { end.1 } else { usize::MAX };
+            line.chars()
+                .skip(from)
+                .take(to.saturating_sub(from))
+                .collect()
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
 #[derive(Default)]
 pub struct EditorWidget {}
 
@@ -457,7 +2887,7 @@ impl StatefulWidget for EditorWidget {
         state
             .editor
             .set_cursor_style(state.mode.focused_cursor_style());
-        state.editor.render(area, buf);
+        state.render(area, buf);
     }
 }
 
@@ -476,6 +2906,155 @@ impl StatefulWidget for EditorUnfocused {
         state
             .editor
             .set_cursor_style(state.mode.unfocused_cursor_style());
-        state.editor.render(area, buf);
+        state.render(area, buf);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+    use super::*;
+
+    /// Type `keys` written as in Vim, with <Esc>, <BS>, <CR> and <C-x> for the keys without a character of their own
+    fn feed(state: &mut EditorState, keys: &str) {
+        let mut rest = keys;
+        while let Some(c) = rest.chars().next() {
+            let (code, modifiers, len) = match rest.find('>') {
+                Some(end) if c == '<' && end > 1 => {
+                    let (code, modifiers) = match &rest[1..end] {
+                        "Esc" => (KeyCode::Esc, KeyModifiers::NONE),
+                        "BS" => (KeyCode::Backspace, KeyModifiers::NONE),
+                        "CR" => (KeyCode::Enter, KeyModifiers::NONE),
+                        name => match name.strip_prefix("C-").and_then(|key| key.chars().next()) {
+                            Some(key) => (KeyCode::Char(key), KeyModifiers::CONTROL),
+                            None => panic!("unknown key <{}>", name),
+                        },
+                    };
+                    (code, modifiers, end + 1)
+                }
+                _ => {
+                    let modifiers = match c.is_uppercase() {
+                        true => KeyModifiers::SHIFT,
+                        false => KeyModifiers::NONE,
+                    };
+                    (KeyCode::Char(c), modifiers, c.len_utf8())
+                }
+            };
+            state.input(Event::Key(KeyEvent::new(code, modifiers)));
+            rest = &rest[len..];
+        }
+    }
+
+    /// Editor of `text` after typing `keys`
+    fn typed(text: &str, keys: &str) -> EditorState {
+        let mut state = EditorState::new(text.to_string(), false);
+        feed(&mut state, keys);
+        state
+    }
+
+    #[test]
+    fn dot_repeats_the_last_change() {
+        let state = typed("one two three", "ciwX<Esc>w.");
+        assert_eq!(state.text(), "X X three\n");
+        // The count of . replaces the count of the change
+        let state = typed("a b c d e", "dw3.");
+        assert_eq!(state.text(), "e\n");
+    }
+
+    #[test]
+    fn macros_replay_the_recorded_keys() {
+        let state = typed("a\nb\nc", "qaA!<Esc>jq2@a");
+        assert_eq!(state.text(), "a!\nb!\nc!\n");
+    }
+
+    #[test]
+    fn replace_mode_is_undone_at_once() {
+        let mut state = typed("xyz", "Rabc<BS><Esc>");
+        assert_eq!(state.text(), "abz\n");
+        feed(&mut state, "u");
+        assert_eq!(state.text(), "xyz\n");
+    }
+
+    #[test]
+    fn a_paste_is_undone_in_one_step() {
+        let mut state = typed("start", "A x");
+        state.input(Event::Paste("\none\r\ntwo".to_string()));
+        assert_eq!(state.text(), "start x\none\ntwo\n");
+        feed(&mut state, "<Esc>u");
+        assert_eq!(state.text(), "start x\n");
+    }
+
+    #[test]
+    fn surroundings_are_added_changed_and_deleted() {
+        let mut state = typed("say hi", "wysiw*");
+        assert_eq!(state.text(), "say *hi*\n");
+        feed(&mut state, "cs*\"");
+        assert_eq!(state.text(), "say \"hi\"\n");
+        feed(&mut state, "ds\"");
+        assert_eq!(state.text(), "say hi\n");
+    }
+
+    #[test]
+    fn long_lines_are_edited_past_the_jumps_of_the_text_area() {
+        let long = "a".repeat(70_000);
+        let state = typed(&format!("{} word b", long), "$ciwX<Esc>");
+        assert_eq!(state.text(), format!("{} word X\n", long));
+        let state = typed(&format!("b\n{} b", long), ":%s/b/X/<CR>");
+        assert_eq!(state.text(), format!("X\n{} X\n", long));
+    }
+
+    #[test]
+    fn block_inserts_on_ragged_lines() {
+        let state = typed("ab\nabcd\na", "l<C-v>jjA!<Esc>");
+        assert_eq!(state.text(), "ab!\nab!cd\na !\n");
+        let state = typed("ab\nabcd\na", "<C-v>jj$A!<Esc>");
+        assert_eq!(state.text(), "ab!\nabcd!\na!\n");
+        let state = typed("abc\ndef\nghi", "<C-v>jj$A!<Esc>");
+        assert_eq!(state.text(), "abc!\ndef!\nghi!\n");
+        // I leaves the lines not reaching the block
+        let state = typed("abcd\nab\nabcd", "ll<C-v>jjI!<Esc>");
+        assert_eq!(state.text(), "ab!cd\nab\nab!cd\n");
+        let state = typed("abcd\nab\nabcd", "ll<C-v>jj$I!<Esc>");
+        assert_eq!(state.text(), "ab!cd\nab\nab!cd\n");
+    }
+
+    #[test]
+    fn recall_waits_for_the_first_or_last_line() {
+        let mut state = EditorState::new("one\ntwo".to_string(), false);
+        state.mode = Mode::Modeless;
+        state.set_recall(vec!["sent".to_string()]);
+        feed(&mut state, "<C-n>");
+        assert_eq!(state.editor.cursor().0, 1);
+        feed(&mut state, "<C-p>");
+        assert_eq!(
+            (state.text(), state.editor.cursor().0),
+            ("one\ntwo\n".to_string(), 0)
+        );
+        feed(&mut state, "<C-p>");
+        assert_eq!(state.text(), "sent\n");
+    }
+
+    #[test]
+    fn huge_counts_stop_once_nothing_changes() {
+        let state = typed("abc", "9999999999l");
+        assert_eq!(state.editor.cursor().0, 0);
+        let mut state = typed("abc\ndef", "99999999999j");
+        assert_eq!(state.editor.cursor().0, 1);
+        feed(&mut state, "999999999gk");
+        assert_eq!(state.editor.cursor().0, 0);
+        feed(&mut state, "x999999999u");
+        assert_eq!(state.text(), "abc\ndef\n");
+        feed(&mut state, "\"cyl999999999\"cp");
+        assert_eq!(state.text(), "abc\ndef\n");
+        assert_eq!(state.message.as_deref(), Some("Resulting text too long"));
+    }
+
+    #[test]
+    fn huge_counts_of_searches_go_round() {
+        let state = typed("abc abc abc", "/abc<CR>999999999n");
+        assert_eq!(state.editor.cursor(), (0, 4));
+        let state = typed("abc abc abc", "/abc<CR>1000n");
+        assert_eq!(state.editor.cursor(), (0, 8));
     }
 }
@@ -0,0 +1,91 @@
+// Completion of the word before the cursor with a placeholder macro or a persona name, in a popup below the cursor
+
+use ratatui::{
+    buffer::Buffer,
+    layout::{Position, Rect},
+    style::{Color, Style},
+    text::Line,
+    widgets::{Block, Borders, Clear, Paragraph, Widget},
+};
+
+use super::text_object::Pos;
+
+const MAX_SHOWN: usize = 6;
+
+/// Matches of the word typed before the cursor, the selected one being inserted in its place
+pub struct Completion {
+    pub start: Pos,     // Start of the completed word
+    pub prefix: String, // Word as it was typed, put back when the completion is cancelled
+    pub matches: Vec<String>,
+    pub selected: usize,
+}
+
+impl Completion {
+    /// End of the match inserted in the text
+    pub fn end(&self) -> Pos {
+        let (row, col) = self.start;
+        (row, col + self.matches[self.selected].chars().count())
+    }
+}
+
+/// Column where the word before `col` starts, and the word. Macros count as words, braces included.
+pub fn word_before(line: &str, col: usize) -> (usize, String) {
+    let chars: Vec<char> = line.chars().take(col).collect();
+    let start = chars
+        .iter()
+        .rposition(|&c| !(c.is_alphanumeric() || c == '_' || c == '{'))
+        .map_or(0, |i| i + 1);
+    (start, chars[start..].iter().collect())
+}
+
+/// Candidates starting with the word, ignoring case and the braces of the macros. Only macros match a word
+/// starting with a brace.
+pub fn matches(candidates: &[String], word: &str) -> Vec<String> {
+    let key = |text: &str| text.trim_start_matches('{').to_lowercase();
+    let typed = key(word);
+    let brace = word.starts_with('{');
+    if typed.is_empty() && !brace {
+        return Vec::new();
+    }
+    candidates
+        .iter()
+        .filter(|candidate| !brace || candidate.starts_with('{'))
+        .filter(|candidate| key(candidate).starts_with(&typed) && *candidate != word)
+        .cloned()
+        .collect()
+}
+
+/// Draw the matches below the cursor, or above it when there is no room below
+pub fn render(completion: &Completion, cursor: Position, buf: &mut Buffer) {
+    let screen = buf.area;
+    let first = completion.selected.saturating_sub(MAX_SHOWN - 1);
+    let shown: Vec<Line> = completion
+        .matches
+        .iter()
+        .enumerate()
+        .skip(first)
+        .take(MAX_SHOWN)
+        .map(|(i, candidate)| match i == completion.selected {
+            true => Line::styled(candidate.as_str(), Style::default().bg(Color::Blue)),
+            false => Line::raw(candidate.as_str()),
+        })
+        .collect();
+    let width = completion
+        .matches
+        .iter()
+        .map(|candidate| candidate.chars().count() as u16 + 2)
+        .max()
+        .unwrap_or(2)
+        .min(screen.width);
+    let height = (shown.len() as u16 + 2).min(screen.height);
+    let y = match cursor.y + 1 + height <= screen.bottom() {
+        true => cursor.y + 1,
+        false => cursor.y.saturating_sub(height),
+    };
+    let x = cursor.x.min(screen.right().saturating_sub(width));
+    let area = Rect::new(x, y, width, height).intersection(screen);
+    Clear.render(area, buf);
+    Paragraph::new(shown)
+        .block(Block::default().borders(Borders::ALL))
+        .render(area, buf);
+}
@@ -0,0 +1,52 @@
+// Size of the text being written, shown in the editor border to keep a message within the model's context budget
+
+use crate::config;
+
+/// Counts the tokens of a text as the model would
+pub trait Tokenizer {
+    fn count(&self, text: &str) -> usize;
+}
+
+/// Estimate from the length of the text, with no vocabulary: about four characters per token in English,
+/// and more tokens than words
+pub struct Heuristic;
+
+impl Tokenizer for Heuristic {
+    fn count(&self, text: &str) -> usize {
+        let chars = text.chars().count();
+        let words = text.split_whitespace().count();
+        chars.div_ceil(4).max((words * 4).div_ceil(3))
+    }
+}
+
+/// Estimate from a fixed number of characters per token, set in the configuration for models or languages the
+/// heuristic is off for
+pub struct CharsPerToken(pub f32);
+
+impl Tokenizer for CharsPerToken {
+    fn count(&self, text: &str) -> usize {
+        (text.chars().count() as f32 / self.0).ceil() as usize
+    }
+}
+
+/// Tokenizer chosen in the configuration
+pub fn configured_tokenizer() -> Box<dyn Tokenizer> {
+    match config::get().chars_per_token {
+        Some(ratio) => Box::new(CharsPerToken(ratio)),
+        None => Box::new(Heuristic),
+    }
+}
+
+pub struct Count {
+    pub chars: usize,
+    pub words: usize,
+    pub tokens: usize,
+}
+
+pub fn count(text: &str, tokenizer: &dyn Tokenizer) -> Count {
+    Count {
+        chars: text.chars().count(),
+        words: text.split_whitespace().count(),
+        tokens: tokenizer.count(text),
+    }
+}
@@ -0,0 +1,275 @@
+// Ex commands typed on the : prompt of the editor
+
+use regex::{Regex, RegexBuilder};
+
+/// First and last rows covered by a command, inclusive
+pub type Range = (usize, usize);
+
+pub enum Command {
+    Write,
+    Quit,
+    NoHighlight,
+    Line(usize),
+    Substitute(Range, Substitution),
+    Set(Setting),
+}
+
+/// Display option changed by :set, as `wrap` or `nowrap`
+pub enum Setting {
+    Wrap(bool),
+    Number(bool),
+    RelativeNumber(bool),
+}
+
+pub struct Substitution {
+    pub pattern: String, // Empty to use the last search pattern
+    pub replacement: String,
+    pub global: bool,
+    pub ignore_case: bool,
+}
+
+impl Substitution {
+    pub fn regex(&self, last_search: &str) -> Result<Regex, String> {
+        let pattern = match self.pattern.is_empty() {
+            true => last_search,
+            false => &self.pattern,
+        };
+        if pattern.is_empty() {
+            return Err("No previous regular expression".to_string());
+        }
+        RegexBuilder::new(pattern)
+            .case_insensitive(self.ignore_case)
+            .build()
+            .map_err(|_| format!("Invalid pattern: {}", pattern))
+    }
+
+    /// Replacement in the syntax of the regex crate: Vim's & and \1 become ${0} and ${1}
+    pub fn replacement(&self) -> String {
+        let mut replacement = String::new();
+        let mut chars = self.replacement.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '&' => replacement.push_str("${0}"),
+                '$' => replacement.push_str("$$"),
+                '\\' => match chars.next() {
+                    Some(d @ '0'..='9') => replacement.push_str(&format!("${{{}}}", d)),
+                    Some('n' | 'r') => replacement.push('\n'),
+                    Some('t') => replacement.push('\t'),
+                    Some('$') => replacement.push_str("$$"),
+                    Some(c) => replacement.push(c),
+                    None => replacement.push('\\'),
+                },
+                c => replacement.push(c),
+            }
+        }
+        replacement
+    }
+}
+
+/// Editor state needed to resolve line addresses
+pub struct Context {
+    pub cursor_row: usize,
+    pub last_row: usize,
+    pub visual: Option<Range>, // Lines of the selection the prompt was opened from, for '<,'>
+}
+
+pub fn parse(command: &str, context: &Context) -> Result<Command, String> {
+    let command = command.trim();
+    let (range, rest) = parse_range(command, context)?;
+    match rest.trim() {
+        "" => match range {
+            Some((_, last)) => Ok(Command::Line(last)),
+            None => Err("No command".to_string()),
+        },
+        "w" | "write" | "wq" | "x" | "xit" => Ok(Command::Write),
+        "q" | "q!" | "quit" | "quit!" => Ok(Command::Quit),
+        "noh" | "nohlsearch" => Ok(Command::NoHighlight),
+        rest if rest.starts_with("se ") || rest.starts_with("set ") => {
+            let option = rest.split_once(' ').map_or("", |(_, option)| option.trim());
+            let (option, on) = match option.strip_prefix("no") {
+                Some(option) => (option, false),
+                None => (option, true),
+            };
+            match option {
+                "wrap" => Ok(Command::Set(Setting::Wrap(on))),
+                "nu" | "number" => Ok(Command::Set(Setting::Number(on))),
+                "rnu" | "relativenumber" => Ok(Command::Set(Setting::RelativeNumber(on))),
+                option => Err(format!("Unknown option: {}", option)),
+            }
+        }
+        rest => match rest.strip_prefix("substitute").or(rest.strip_prefix('s')) {
+            Some(arguments) => {
+                let range = range.unwrap_or((context.cursor_row, context.cursor_row));
+                Ok(Command::Substitute(range, parse_substitution(arguments)?))
+            }
+            None => Err(format!("Not an editor command: {}", command)),
+        },
+    }
+}
+
+fn parse_range<'a>(
+    command: &'a str,
+    context: &Context,
+) -> Result<(Option<Range>, &'a str), String> {
+    if let Some(rest) = command.strip_prefix('%') {
+        return Ok((Some((0, context.last_row)), rest));
+    }
+    let (first, rest) = parse_address(command, context)?;
+    let Some(first) = first else {
+        return Ok((None, rest));
+    };
+    match rest.strip_prefix(',') {
+        Some(rest) => {
+            let (last, rest) = parse_address(rest, context)?;
+            let last = last.ok_or("Invalid range")?;
+            Ok((Some((first.min(last), first.max(last))), rest))
+        }
+        None => Ok((Some((first, first)), rest)),
+    }
+}
+
+fn parse_address<'a>(
+    command: &'a str,
+    context: &Context,
+) -> Result<(Option<usize>, &'a str), String> {
+    let visual = || context.visual.ok_or("Mark not set");
+    if let Some(rest) = command.strip_prefix('.') {
+        Ok((Some(context.cursor_row), rest))
+    } else if let Some(rest) = command.strip_prefix('$') {
+        Ok((Some(context.last_row), rest))
+    } else if let Some(rest) = command.strip_prefix("'<") {
+        Ok((Some(visual()?.0), rest))
+    } else if let Some(rest) = command.strip_prefix("'>") {
+        Ok((Some(visual()?.1), rest))
+    } else {
+        let digits = command.len()
+            - command
+                .trim_start_matches(|c: char| c.is_ascii_digit())
+                .len();
+        match command[..digits].parse::<usize>() {
+            Ok(line) => {
+                let row = line.saturating_sub(1).min(context.last_row);
+                Ok((Some(row), &command[digits..]))
+            }
+            Err(_) => Ok((None, command)),
+        }
+    }
+}
+
+/// Parse the /pattern/replacement/flags part of :s. Any punctuation can be used instead of /
+fn parse_substitution(arguments: &str) -> Result<Substitution, String> {
+    let mut chars = arguments.chars();
+    let delimiter = chars
+        .next()
+        .filter(|c| !c.is_alphanumeric() && !c.is_whitespace() && *c != '\\')
+        .ok_or("Usage: s/pattern/replacement/flags")?;
+
+    let mut parts = vec![String::new()];
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(c) if c == delimiter => parts.last_mut().unwrap().push(c),
+                Some(c) => {
+                    parts.last_mut().unwrap().push('\\');
+                    parts.last_mut().unwrap().push(c);
+                }
+                None => parts.last_mut().unwrap().push('\\'),
+            },
+            c if c == delimiter && parts.len() < 3 => parts.push(String::new()),
+            c => parts.last_mut().unwrap().push(c),
+        }
+    }
+    parts.resize(3, String::new());
+    let flags = parts.pop().unwrap_or_default();
+    let replacement = parts.pop().unwrap_or_default();
+    let pattern = parts.pop().unwrap_or_default();
+
+    let mut substitution = Substitution {
+        pattern,
+        replacement,
+        global: false,
+        ignore_case: false,
+    };
+    for flag in flags.trim().chars() {
+        match flag {
+            'g' => substitution.global = true,
+            'i' => substitution.ignore_case = true,
+            'I' => substitution.ignore_case = false,
+            flag => return Err(format!("Invalid flag: {}", flag)),
+        }
+    }
+    Ok(substitution)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONTEXT: Context = Context {
+        cursor_row: 4,
+        last_row: 9,
+        visual: Some((2, 3)),
+    };
+
+    fn substitute(command: &str) -> (Range, Substitution) {
+        match parse(command, &CONTEXT) {
+            Ok(Command::Substitute(range, substitution)) => (range, substitution),
+            _ => panic!("{} is not a substitution", command),
+        }
+    }
+
+    #[test]
+    fn commands() {
+        assert!(matches!(parse("w", &CONTEXT), Ok(Command::Write)));
+        assert!(matches!(parse(" q! ", &CONTEXT), Ok(Command::Quit)));
+        assert!(matches!(parse("noh", &CONTEXT), Ok(Command::NoHighlight)));
+        assert!(matches!(parse("12", &CONTEXT), Ok(Command::Line(9))));
+        assert!(matches!(parse("3", &CONTEXT), Ok(Command::Line(2))));
+        assert!(parse("", &CONTEXT).is_err());
+        assert!(parse("frobnicate", &CONTEXT).is_err());
+    }
+
+    #[test]
+    fn ranges() {
+        assert_eq!(substitute("s/a/b/").0, (4, 4));
+        assert_eq!(substitute("%s/a/b/").0, (0, 9));
+        assert_eq!(substitute("3,.s/a/b/").0, (2, 4));
+        assert_eq!(substitute("$,2s/a/b/").0, (1, 9));
+        assert_eq!(substitute("'<,'>s/a/b/").0, (2, 3));
+        let unselected = Context {
+            visual: None,
+            ..CONTEXT
+        };
+        assert!(parse("'<,'>s/a/b/", &unselected).is_err());
+    }
+
+    #[test]
+    fn substitutions() {
+        let (_, substitution) = substitute("s#a\\#b#c#gi");
+        assert_eq!(substitution.pattern, "a#b");
+        assert_eq!(substitution.replacement, "c");
+        assert!(substitution.global && substitution.ignore_case);
+
+        let (_, substitution) = substitute("substitute/x\\.y");
+        assert_eq!(substitution.pattern, "x\\.y");
+        assert_eq!(substitution.replacement, "");
+        assert!(!substitution.global && !substitution.ignore_case);
+
+        assert!(parse("s/a/b/z", &CONTEXT).is_err());
+        assert!(parse("s", &CONTEXT).is_err());
+    }
+
+    #[test]
+    fn replacements() {
+        let (_, substitution) = substitute("s/(a)/[&\\1$\\n]/");
+        assert_eq!(substitution.replacement(), "[${0}${1}$$\n]");
+    }
+
+    #[test]
+    fn empty_pattern() {
+        let (_, substitution) = substitute("s//b/");
+        let regex = substitution.regex("a+").map(|regex| regex.to_string());
+        assert_eq!(regex, Ok("a+".to_string()));
+        assert!(substitution.regex("").is_err());
+    }
+}
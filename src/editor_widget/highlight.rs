@@ -0,0 +1,94 @@
+// Roleplay markup styled as the sent messages are: libmoon splits the text being written into its spans, and the
+// editor takes the style of each character from them. A marker that is never closed is flagged where it opens.
+
+use libmoon::message::Message;
+use ratatui::style::{Color, Style};
+
+use super::text_object::Pos;
+use crate::chat_widget::message_style;
+
+const UNCLOSED: Style = Style::new().fg(Color::White).bg(Color::Red);
+
+/// Styles of the text of the editor, worked out again only when the text changes
+#[derive(Default)]
+pub struct Highlight {
+    lines: Vec<String>, // Text the styles were worked out for
+    styles: Vec<Vec<Style>>,
+    unclosed: Vec<(Pos, char)>,
+}
+
+impl Highlight {
+    /// Style of each character of each line, and the markers left open
+    pub fn update(&mut self, lines: &[String]) -> (&[Vec<Style>], &[(Pos, char)]) {
+        if self.lines != lines {
+            self.lines = lines.to_vec();
+            self.unclosed = unclosed(lines);
+            self.styles = styles(lines);
+            for &((row, col), _) in &self.unclosed {
+                if let Some(style) = self.styles[row].get_mut(col) {
+                    *style = UNCLOSED;
+                }
+            }
+        }
+        (&self.styles, &self.unclosed)
+    }
+}
+
+/// Style of each character of each line, from the spans of the text as a message. The markers left out of the
+/// spans take the style of the text that follows them.
+fn styles(lines: &[String]) -> Vec<Vec<Style>> {
+    let message = Message {
+        text: lines.join("\n"),
+        owner_name: String::new(),
+    };
+    let mut spans = Vec::new();
+    for (i, line) in message.spans().into_iter().enumerate() {
+        if i > 0 {
+            spans.push(('\n', Style::default()));
+        }
+        for (text, style) in line {
+            let style = message_style(style);
+            spans.extend(text.chars().map(|c| (c, style)));
+        }
+    }
+
+    let mut spans = spans.into_iter().peekable();
+    let mut style = Style::default();
+    let mut styles = vec![Vec::new()];
+    for c in message.text.chars() {
+        if let Some(&(span_char, span_style)) = spans.peek() {
+            style = span_style;
+            if span_char == c {
+                spans.next();
+            }
+        }
+        match c {
+            '\n' => styles.push(Vec::new()),
+            _ => styles.last_mut().unwrap().push(style),
+        }
+    }
+    styles
+}
+
+/// Markers opening an action or speech that is not closed. A marker stays open across line breaks.
+fn unclosed(lines: &[String]) -> Vec<(Pos, char)> {
+    let mut strong: Option<Pos> = None;
+    let mut quote: Option<Pos> = None;
+    for (row, line) in lines.iter().enumerate() {
+        for (col, c) in line.chars().enumerate() {
+            let open = match c {
+                '*' => &mut strong,
+                '"' => &mut quote,
+                _ => continue,
+            };
+            *open = match open {
+                Some(_) => None,
+                None => Some((row, col)),
+            };
+        }
+    }
+    [(strong, '*'), (quote, '"')]
+        .into_iter()
+        .filter_map(|(open, marker)| open.map(|pos| (pos, marker)))
+        .collect()
+}
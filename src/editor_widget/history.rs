@@ -0,0 +1,58 @@
+// Undo history of the editor. tui-textarea records every single edit, while Vim undoes a whole command at once
+// (an insert session, a dd, a :s...), so the text is saved before each command instead.
+
+use super::text_object::Pos;
+
+const MAX_HISTORIES: usize = 1000;
+
+#[derive(Clone, PartialEq)]
+pub struct Snapshot {
+    pub lines: Vec<String>,
+    pub cursor: Pos,
+}
+
+#[derive(Default)]
+pub struct History {
+    undo: Vec<Snapshot>,
+    redo: Vec<Snapshot>,
+    left: Option<Snapshot>, // Text of the editor when it was closed, for a later editor to continue the history
+}
+
+impl History {
+    /// Save the text as it was before a command, forgetting the changes that were undone
+    pub fn push(&mut self, before: Snapshot) {
+        if self.undo.len() >= MAX_HISTORIES {
+            self.undo.remove(0);
+        }
+        self.undo.push(before);
+        self.redo.clear();
+    }
+
+    /// Text to restore to undo the last command, `current` being kept for redo
+    pub fn undo(&mut self, current: Snapshot) -> Option<Snapshot> {
+        let snapshot = self.undo.pop()?;
+        self.redo.push(current);
+        Some(snapshot)
+    }
+
+    pub fn redo(&mut self, current: Snapshot) -> Option<Snapshot> {
+        let snapshot = self.redo.pop()?;
+        self.undo.push(current);
+        Some(snapshot)
+    }
+
+    /// Keep the history of an editor being closed with the text it has
+    pub fn leave(&mut self, current: Snapshot) {
+        self.left = Some(current);
+    }
+
+    /// Continue the history in an editor opened with `current`. When it is not the text the history was left
+    /// with, that text becomes the first change to undo.
+    pub fn resume(&mut self, current: &Snapshot) {
+        if let Some(left) = self.left.take()
+            && left.lines != current.lines
+        {
+            self.push(left);
+        }
+    }
+}
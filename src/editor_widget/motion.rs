@@ -0,0 +1,98 @@
+// Motions that tui-textarea's CursorMove does not cover
+
+use super::text_object::{self, Pos};
+
+/// Column reached by the character search `kind` (one of f, F, t or T) for `target` on `line`.
+/// `repeat` is set for ; and , so that t and T do not get stuck next to the character they already found.
+pub fn find_char(
+    line: &str,
+    col: usize,
+    (kind, target): (char, char),
+    count: usize,
+    repeat: bool,
+) -> Option<usize> {
+    let chars: Vec<char> = line.chars().collect();
+    let till = matches!(kind, 't' | 'T');
+    let skip = usize::from(till && repeat);
+    match kind {
+        'f' | 't' => {
+            let found = (col + 1 + skip..chars.len())
+                .filter(|&i| chars[i] == target)
+                .nth(count.saturating_sub(1))?;
+            Some(if till { found - 1 } else { found })
+        }
+        _ => {
+            let found = (0..col.saturating_sub(skip))
+                .rev()
+                .filter(|&i| chars[i] == target)
+                .nth(count.saturating_sub(1))?;
+            Some(if till { found + 1 } else { found })
+        }
+    }
+}
+
+/// Reverse the direction of a character search, for ,
+pub fn reverse_find(kind: char) -> char {
+    match kind {
+        'f' => 'F',
+        'F' => 'f',
+        't' => 'T',
+        _ => 't',
+    }
+}
+
+/// Position of the bracket or quote matching the first one at or after the cursor on its line, for %
+pub fn matching_pair(lines: &[String], (row, col): Pos) -> Option<Pos> {
+    let (col, c) = lines
+        .get(row)?
+        .chars()
+        .enumerate()
+        .skip(col)
+        .find(|(_, c)| "()[]{}\"*".contains(*c))?;
+    let (start, end) = text_object::range(lines, (row, col), c, false, 1)?;
+    let open = (start.0, start.1 - 1);
+    match open == (row, col) {
+        true => Some(end),
+        false => Some(open),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_forward() {
+        let line = "a,b,c,d";
+        assert_eq!(find_char(line, 0, ('f', ','), 1, false), Some(1));
+        assert_eq!(find_char(line, 0, ('f', ','), 3, false), Some(5));
+        assert_eq!(find_char(line, 0, ('f', ','), 4, false), None);
+        assert_eq!(find_char(line, 0, ('t', ','), 2, false), Some(2));
+    }
+
+    #[test]
+    fn find_backward() {
+        let line = "a,b,c,d";
+        assert_eq!(find_char(line, 6, ('F', ','), 1, false), Some(5));
+        assert_eq!(find_char(line, 6, ('F', ','), 2, false), Some(3));
+        assert_eq!(find_char(line, 6, ('T', ','), 1, false), Some(6));
+        assert_eq!(find_char(line, 0, ('F', ','), 1, false), None);
+    }
+
+    #[test]
+    fn repeated_till_moves_past_the_found_character() {
+        let line = "a,b,c";
+        // After t, the cursor is before the comma: ; goes on to the next one
+        assert_eq!(find_char(line, 0, ('t', ','), 1, true), Some(2));
+        assert_eq!(find_char(line, 4, ('T', ','), 1, true), Some(2));
+    }
+
+    #[test]
+    fn matching_pairs() {
+        let lines = vec!["f(a, [b]) x".to_string()];
+        assert_eq!(matching_pair(&lines, (0, 0)), Some((0, 8)));
+        assert_eq!(matching_pair(&lines, (0, 8)), Some((0, 1)));
+        assert_eq!(matching_pair(&lines, (0, 5)), Some((0, 7)));
+        assert_eq!(matching_pair(&lines, (0, 9)), None);
+    }
+}
@@ -0,0 +1,77 @@
+// Recall of the messages sent before, browsed and searched from the editor like the history of a shell
+
+#[derive(Default)]
+pub struct Recall {
+    entries: Vec<String>,                           // Oldest first
+    index: Option<usize>, // Entry shown in the editor, None for the text being written
+    draft: String,        // Text being written when the first entry was recalled
+    before_search: Option<(Option<usize>, String)>, // Entry and text shown when Ctrl-R was typed
+}
+
+impl Recall {
+    pub fn new(entries: Vec<String>) -> Self {
+        Self {
+            entries,
+            ..Default::default()
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Index of the entry shown, the number of entries standing for the draft
+    pub fn position(&self) -> usize {
+        self.index.unwrap_or(self.entries.len())
+    }
+
+    /// Entry before (`older`) or after the one shown, or the draft when moving past the newest entry
+    pub fn step(&mut self, current: &str, older: bool) -> Option<String> {
+        match (self.index, older) {
+            (None, true) => {
+                let index = self.entries.len().checked_sub(1)?;
+                self.draft = current.to_string();
+                self.index = Some(index);
+            }
+            (Some(0), true) | (None, false) => return None,
+            (Some(index), true) => self.index = Some(index - 1),
+            (Some(index), false) if index + 1 < self.entries.len() => self.index = Some(index + 1),
+            (Some(_), false) => {
+                self.index = None;
+                return Some(std::mem::take(&mut self.draft));
+            }
+        }
+        self.index.map(|index| self.entries[index].clone())
+    }
+
+    /// Position the search started from, searched again each time the query changes
+    pub fn search_start(&self) -> usize {
+        match &self.before_search {
+            Some((Some(index), _)) => *index,
+            _ => self.entries.len(),
+        }
+    }
+
+    pub fn start_search(&mut self, current: &str) {
+        self.before_search = Some((self.index, current.to_string()));
+    }
+
+    /// Newest entry containing `query` before the entry at `before`
+    pub fn search(&mut self, query: &str, before: usize, current: &str) -> Option<String> {
+        let index = (0..before.min(self.entries.len()))
+            .rev()
+            .find(|&i| self.entries[i].contains(query))?;
+        if self.index.is_none() {
+            self.draft = current.to_string();
+        }
+        self.index = Some(index);
+        Some(self.entries[index].clone())
+    }
+
+    /// Go back to what was shown before the search, returning its text
+    pub fn cancel_search(&mut self) -> Option<String> {
+        let (index, text) = self.before_search.take()?;
+        self.index = index;
+        Some(text)
+    }
+}
@@ -0,0 +1,25 @@
+// Delimiters added, changed and deleted around text by the surround commands (ys, S, cs, ds)
+
+use super::text_object::{self, Pos};
+
+/// Opening and closing delimiters typed as `c`: a bracket is paired with its match, and any other character with
+/// itself, as in *action* or "speech"
+pub fn pair(c: char) -> (char, char) {
+    match c {
+        '(' | ')' | 'b' => ('(', ')'),
+        '[' | ']' => ('[', ']'),
+        '{' | '}' | 'B' => ('{', '}'),
+        '<' | '>' => ('<', '>'),
+        c => (c, c),
+    }
+}
+
+/// Positions of the delimiters typed as `c` around `cursor`
+pub fn delimiters(lines: &[String], cursor: Pos, c: char) -> Option<(Pos, Pos)> {
+    if matches!(c, 'w' | 'W' | 'p') {
+        return None; // Text objects that are not delimited
+    }
+    // The delimiters are right outside of the inner text object
+    let ((row, col), close) = text_object::range(lines, cursor, c, false, 1)?;
+    Some(((row, col - 1), close))
+}
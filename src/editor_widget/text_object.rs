@@ -0,0 +1,305 @@
+// Vim text objects (iw, aw, i", a(, ip, ...), resolved to the range of text they cover
+
+pub type Pos = (usize, usize);
+
+/// Range covered by a text object around `cursor`, `object` being the key typed after i or a.
+/// The end of the range is exclusive.
+pub fn range(
+    lines: &[String],
+    cursor: Pos,
+    object: char,
+    around: bool,
+    count: usize,
+) -> Option<(Pos, Pos)> {
+    match object {
+        'w' => word(lines, cursor, around, count, false),
+        'W' => word(lines, cursor, around, count, true),
+        '"' | '\'' | '`' | '*' => quote(lines, cursor, object, around),
+        '(' | ')' | 'b' => bracket(lines, cursor, ('(', ')'), around, count),
+        '[' | ']' => bracket(lines, cursor, ('[', ']'), around, count),
+        '{' | '}' | 'B' => bracket(lines, cursor, ('{', '}'), around, count),
+        '<' | '>' => bracket(lines, cursor, ('<', '>'), around, count),
+        'p' => paragraph(lines, cursor, around, count),
+        _ => None,
+    }
+}
+
+#[derive(PartialEq)]
+enum Class {
+    Space,
+    Word,
+    Punctuation,
+}
+
+fn class(c: char, big: bool) -> Class {
+    if c.is_whitespace() {
+        Class::Space
+    } else if big || c.is_alphanumeric() || c == '_' {
+        Class::Word
+    } else {
+        Class::Punctuation
+    }
+}
+
+fn word(
+    lines: &[String],
+    cursor: Pos,
+    around: bool,
+    count: usize,
+    big: bool,
+) -> Option<(Pos, Pos)> {
+    let (row, col) = cursor;
+    let line: Vec<char> = lines.get(row)?.chars().collect();
+    if line.is_empty() {
+        return None;
+    }
+    let col = col.min(line.len() - 1);
+    let run_end = |start: usize| {
+        let c = class(line[start], big);
+        (start..line.len())
+            .find(|&i| class(line[i], big) != c)
+            .unwrap_or(line.len())
+    };
+    let is_space = |i: usize| class(line[i], big) == Class::Space;
+
+    let cursor_class = class(line[col], big);
+    let mut start = (0..col)
+        .rev()
+        .find(|&i| class(line[i], big) != cursor_class)
+        .map_or(0, |i| i + 1);
+    let mut end = run_end(col);
+
+    if around {
+        if cursor_class == Class::Space {
+            // On white space, aw selects the white space and the following word
+            if end < line.len() {
+                end = run_end(end);
+            }
+        } else if end < line.len() && is_space(end) {
+            end = run_end(end);
+        } else {
+            // No trailing white space, take the leading one instead
+            while start > 0 && is_space(start - 1) {
+                start -= 1;
+            }
+        }
+    }
+    for _ in 1..count {
+        if end >= line.len() {
+            break;
+        }
+        end = run_end(end);
+        if around && end < line.len() && is_space(end) {
+            end = run_end(end);
+        }
+    }
+    Some(((row, start), (row, end)))
+}
+
+fn quote(lines: &[String], cursor: Pos, quote: char, around: bool) -> Option<(Pos, Pos)> {
+    let (row, col) = cursor;
+    let line: Vec<char> = lines.get(row)?.chars().collect();
+    // A run of quotes is a single delimiter, as the asterisks around **strong** text
+    let mut runs: Vec<(usize, usize)> = Vec::new();
+    for i in (0..line.len()).filter(|&i| line[i] == quote) {
+        match runs.last_mut() {
+            Some((_, end)) if *end == i => *end += 1,
+            _ => runs.push((i, i + 1)),
+        }
+    }
+    let pairs = || runs.chunks_exact(2).map(|pair| (pair[0], pair[1]));
+    let (open, close) = pairs()
+        .find(|&(open, close)| open.0 <= col && col < close.1)
+        .or_else(|| {
+            // Like Vim, fall back to the first quoted text after the cursor
+            pairs().find(|&(open, _)| open.0 > col)
+        })?;
+
+    if !around {
+        return Some(((row, open.1), (row, close.0)));
+    }
+    let mut start = open.0;
+    let mut end = close.1;
+    if end < line.len() && line[end].is_whitespace() {
+        while end < line.len() && line[end].is_whitespace() {
+            end += 1;
+        }
+    } else {
+        while start > 0 && line[start - 1].is_whitespace() {
+            start -= 1;
+        }
+    }
+    Some(((row, start), (row, end)))
+}
+
+fn bracket(
+    lines: &[String],
+    cursor: Pos,
+    (open, close): (char, char),
+    around: bool,
+    count: usize,
+) -> Option<(Pos, Pos)> {
+    let chars: Vec<(Pos, char)> = lines
+        .iter()
+        .enumerate()
+        .flat_map(|(row, line)| {
+            line.chars()
+                .enumerate()
+                .map(move |(col, c)| ((row, col), c))
+        })
+        .collect();
+    let at = chars.partition_point(|&(pos, _)| pos < cursor);
+
+    // Search backward for the unmatched opening bracket
+    let find_open = |from: usize| {
+        let mut depth = 0;
+        for i in (0..from).rev() {
+            match chars[i].1 {
+                c if c == close => depth += 1,
+                c if c == open && depth == 0 => return Some(i),
+                c if c == open => depth -= 1,
+                _ => (),
+            }
+        }
+        None
+    };
+    let mut open_at = match chars.get(at) {
+        Some(&((row, col), c)) if (row, col) == cursor && c == open => at,
+        _ => find_open(at)?,
+    };
+    for _ in 1..count {
+        open_at = find_open(open_at)?;
+    }
+
+    let mut depth = 0;
+    let mut close_at = None;
+    for (i, &(_, c)) in chars.iter().enumerate().skip(open_at + 1) {
+        match c {
+            c if c == open => depth += 1,
+            c if c == close && depth == 0 => {
+                close_at = Some(i);
+                break;
+            }
+            c if c == close => depth -= 1,
+            _ => (),
+        }
+    }
+
+    let ((open_row, open_col), _) = chars[open_at];
+    let ((close_row, close_col), _) = chars[close_at?];
+    match around {
+        true => Some(((open_row, open_col), (close_row, close_col + 1))),
+        false => Some(((open_row, open_col + 1), (close_row, close_col))),
+    }
+}
+
+fn paragraph(lines: &[String], cursor: Pos, around: bool, count: usize) -> Option<(Pos, Pos)> {
+    let blank = |row: usize| lines[row].trim().is_empty();
+    let block_end = |start: usize| {
+        (start..lines.len())
+            .find(|&row| blank(row) != blank(start))
+            .unwrap_or(lines.len())
+    };
+
+    let row = cursor.0.min(lines.len().checked_sub(1)?);
+    let mut start = (0..row)
+        .rev()
+        .find(|&r| blank(r) != blank(row))
+        .map_or(0, |r| r + 1);
+    let mut end = block_end(row);
+    for _ in 1..count {
+        if end >= lines.len() {
+            break;
+        }
+        end = block_end(end);
+    }
+    if around {
+        if end < lines.len() {
+            end = block_end(end);
+        } else {
+            // Last paragraph, take the blank lines before it instead
+            while start > 0 && blank(start - 1) != blank(row) {
+                start -= 1;
+            }
+        }
+    }
+
+    // Linewise: the range ends at the start of the line after the paragraph
+    match end < lines.len() {
+        true => Some(((start, 0), (end, 0))),
+        false => Some(((start, 0), (end - 1, lines[end - 1].chars().count()))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &str) -> Vec<String> {
+        text.split('\n').map(str::to_string).collect()
+    }
+
+    #[test]
+    fn inner_and_around_word() {
+        let text = lines("one two three");
+        assert_eq!(range(&text, (0, 5), 'w', false, 1), Some(((0, 4), (0, 7))));
+        assert_eq!(range(&text, (0, 5), 'w', true, 1), Some(((0, 4), (0, 8))));
+        // The last word has no trailing space, aw takes the leading one
+        assert_eq!(range(&text, (0, 9), 'w', true, 1), Some(((0, 7), (0, 13))));
+        assert_eq!(range(&text, (0, 0), 'w', false, 3), Some(((0, 0), (0, 7))));
+    }
+
+    #[test]
+    fn big_word_includes_punctuation() {
+        let text = lines("say foo.bar now");
+        assert_eq!(range(&text, (0, 5), 'w', false, 1), Some(((0, 4), (0, 7))));
+        assert_eq!(range(&text, (0, 5), 'W', false, 1), Some(((0, 4), (0, 11))));
+    }
+
+    #[test]
+    fn quotes() {
+        let text = lines("she says \"hi there\" and *waves* slowly");
+        assert_eq!(
+            range(&text, (0, 12), '"', false, 1),
+            Some(((0, 10), (0, 18)))
+        );
+        assert_eq!(range(&text, (0, 12), '"', true, 1), Some(((0, 9), (0, 20))));
+        // Before any quote, the first quoted text after the cursor
+        assert_eq!(
+            range(&text, (0, 0), '*', false, 1),
+            Some(((0, 25), (0, 30)))
+        );
+        assert_eq!(range(&text, (0, 0), '`', false, 1), None);
+    }
+
+    #[test]
+    fn runs_of_quotes_are_one_delimiter() {
+        let text = lines("a **strong** word");
+        assert_eq!(range(&text, (0, 6), '*', false, 1), Some(((0, 4), (0, 10))));
+        assert_eq!(range(&text, (0, 6), '*', true, 1), Some(((0, 2), (0, 13))));
+        assert_eq!(range(&text, (0, 2), '*', false, 1), Some(((0, 4), (0, 10))));
+    }
+
+    #[test]
+    fn nested_brackets() {
+        let text = lines("f(a, (b), c)");
+        assert_eq!(range(&text, (0, 6), '(', false, 1), Some(((0, 6), (0, 7))));
+        assert_eq!(range(&text, (0, 6), 'b', true, 2), Some(((0, 1), (0, 12))));
+        assert_eq!(range(&text, (0, 0), '(', false, 1), None);
+    }
+
+    #[test]
+    fn brackets_over_lines() {
+        let text = lines("{\n  a\n}");
+        assert_eq!(range(&text, (1, 2), 'B', false, 1), Some(((0, 1), (2, 0))));
+    }
+
+    #[test]
+    fn paragraphs() {
+        let text = lines("a\nb\n\nc\nd");
+        assert_eq!(range(&text, (0, 0), 'p', false, 1), Some(((0, 0), (2, 0))));
+        assert_eq!(range(&text, (0, 0), 'p', true, 1), Some(((0, 0), (3, 0))));
+        // The last paragraph takes the blank lines before it
+        assert_eq!(range(&text, (4, 0), 'p', true, 1), Some(((2, 0), (4, 1))));
+    }
+}
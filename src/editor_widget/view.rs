@@ -0,0 +1,416 @@
+// Rendering of the editor text. tui-textarea can only highlight a charwise selection, so the lines are drawn here
+// to also show the rectangular selection of the visual block mode, to wrap long lines and to number them.
+
+use ratatui::{
+    buffer::Buffer,
+    layout::{Position, Rect},
+    style::{Color, Style},
+    text::{Line, Span, Text},
+    widgets::{Paragraph, Widget},
+};
+use tui_textarea::TextArea;
+use unicode_width::UnicodeWidthChar;
+
+use super::text_object::Pos;
+use crate::config::LineNumbers;
+
+/// Part of the text to highlight as selected
+pub enum Selection {
+    Chars(Pos, Pos), // The end is exclusive
+    Block(Pos, Pos), // Top left and bottom right corners in display columns, inclusive
+}
+
+/// Display settings of an editor, from the configuration and changed with :set
+#[derive(Clone, Copy)]
+pub struct Display {
+    pub wrap: bool,
+    pub numbers: LineNumbers,
+}
+
+/// Part of the text shown in the editor area, kept between frames to only scroll when the cursor leaves it
+#[derive(Default)]
+pub struct Viewport {
+    pub row: usize,
+    pub col: usize,  // First display column shown when lines do not wrap
+    pub skip: usize, // Display lines of the top line hidden above the area when lines wrap
+    pub height: usize,
+    pub width: usize, // Width of the text, without the gutter
+}
+
+impl Viewport {
+    /// Scroll just enough to show the cursor, over the display columns `cells` of its line, as tui-textarea does
+    fn follow(&mut self, row: usize, (first, last): (usize, usize)) {
+        self.row = next_top(self.row, row, self.height);
+        self.col = next_top(self.col, last, self.width).min(first);
+        self.skip = 0;
+    }
+
+    /// Scroll by display lines just enough to show the cursor
+    fn follow_wrapped(&mut self, lines: &[String], (row, col): Pos, tab_len: usize) {
+        self.col = 0;
+        let cursor_segment = segment_of(&wrap_line(&lines[row], self.width, tab_len), col);
+        if self.row >= lines.len() || (row, cursor_segment) < (self.row, self.skip) {
+            (self.row, self.skip) = (row, cursor_segment);
+            return;
+        }
+        // Display lines from the top of the area to the cursor, dropped from the top while they do not fit
+        let mut segments: Vec<usize> = (self.row..row)
+            .map(|row| wrap_line(&lines[row], self.width, tab_len).len())
+            .collect();
+        if let Some(&top) = segments.first() {
+            self.skip = self.skip.min(top - 1); // The top line may have been shortened
+        }
+        let mut shown = segments.iter().sum::<usize>() + cursor_segment + 1 - self.skip;
+        while shown > self.height.max(1) {
+            self.skip += 1;
+            shown -= 1;
+            if self.row < row && self.skip == segments[0] {
+                segments.remove(0);
+                self.row += 1;
+                self.skip = 0;
+            }
+        }
+    }
+}
+
+fn next_top(top: usize, cursor: usize, len: usize) -> usize {
+    if cursor < top {
+        cursor
+    } else if top + len <= cursor {
+        cursor + 1 - len
+    } else {
+        top
+    }
+}
+
+/// Ranges of characters of a line shown on each display line when it wraps at `width` cells, breaking after a space
+/// when there is one. A line filling the last display line gets an empty one for the cursor after its end.
+pub fn wrap_line(line: &str, width: usize, tab_len: usize) -> Vec<(usize, usize)> {
+    let chars: Vec<char> = line.chars().collect();
+    if width == 0 {
+        return vec![(0, chars.len())];
+    }
+    let mut segments = Vec::new();
+    let mut start = 0;
+    loop {
+        let mut end = start;
+        let mut used = 0;
+        while end < chars.len() {
+            let len = char_width(chars[end], used, tab_len);
+            if used + len > width {
+                break;
+            }
+            used += len;
+            end += 1;
+        }
+        if end == chars.len() {
+            segments.push((start, end));
+            if used >= width {
+                segments.push((end, end));
+            }
+            return segments;
+        }
+        let split = (start + 1..=end)
+            .rev()
+            .find(|&i| chars[i - 1].is_whitespace())
+            .unwrap_or(end.max(start + 1));
+        segments.push((start, split));
+        start = split;
+    }
+}
+
+/// Index of the display line showing the column `col`
+pub fn segment_of(segments: &[(usize, usize)], col: usize) -> usize {
+    segments
+        .iter()
+        .rposition(|&(start, _)| start <= col)
+        .unwrap_or(0)
+}
+
+/// Width of the line numbers of `lines` lines in an editor `width` cells wide, with the space after them
+pub fn gutter_width(numbers: LineNumbers, lines: usize, width: usize) -> usize {
+    match numbers {
+        LineNumbers::Off => 0,
+        _ => (lines.to_string().len().max(3) + 1).min(width),
+    }
+}
+
+/// Draw the editor, returning where the cursor is on screen when it is shown
+pub fn render(
+    editor: &mut TextArea,
+    selection: Option<Selection>,
+    markup: Option<&[Vec<Style>]>,
+    display: Display,
+    viewport: &mut Viewport,
+    area: Rect,
+    buf: &mut Buffer,
+) -> Option<Position> {
+    let select_style = editor.selection_style();
+    let inner = match editor.block() {
+        Some(block) => {
+            let inner = block.inner(area);
+            block.render(area, buf);
+            inner
+        }
+        None => area,
+    };
+    let gutter = gutter_width(display.numbers, editor.lines().len(), inner.width as usize);
+    let tab_len = (editor.tab_length() as usize).max(1);
+    viewport.height = inner.height as usize;
+    viewport.width = inner.width as usize - gutter;
+    let (cursor_row, cursor_col) = editor.cursor();
+    match display.wrap {
+        true => viewport.follow_wrapped(editor.lines(), editor.cursor(), tab_len),
+        false => {
+            let line = &editor.lines()[cursor_row];
+            let first = display_width(line.chars().take(cursor_col), tab_len);
+            let width = line
+                .chars()
+                .nth(cursor_col)
+                .map_or(1, |c| char_width(c, first, tab_len));
+            viewport.follow(cursor_row, (first, first + width.max(1) - 1));
+        }
+    }
+
+    let mut cursor = None;
+    let mut numbers = Vec::new();
+    let mut lines = Vec::new();
+    let mut row = viewport.row;
+    let mut skip = viewport.skip;
+    while lines.len() < viewport.height && row < editor.lines().len() {
+        let line_markup = markup.and_then(|markup| markup.get(row)).map(Vec::as_slice);
+        let styles = char_styles(editor, row, &selection, select_style, line_markup);
+        let segments = match display.wrap {
+            true => wrap_line(&editor.lines()[row], viewport.width, tab_len),
+            false => vec![(0, editor.lines()[row].chars().count())],
+        };
+        let last = segments.len() - 1;
+        for (i, &segment) in segments.iter().enumerate().skip(skip) {
+            if lines.len() == viewport.height {
+                break;
+            }
+            if row == cursor_row && i == segment_of(&segments, cursor_col) {
+                let before = editor.lines()[row].chars().take(cursor_col).skip(segment.0);
+                let x = display_width(before, tab_len);
+                cursor = x.checked_sub(viewport.col).map(|x| (x, lines.len()));
+            }
+            lines.push(segment_line(
+                editor,
+                row,
+                segment,
+                i == last,
+                &styles,
+                tab_len,
+            ));
+            numbers.push(match (i, display.numbers) {
+                (0, LineNumbers::Absolute) => Line::raw(format!("{} ", row + 1)),
+                (0, LineNumbers::Relative) if row == cursor_row => {
+                    Line::styled(format!("{} ", row + 1), Color::Yellow)
+                }
+                (0, LineNumbers::Relative) => Line::raw(format!("{} ", row.abs_diff(cursor_row))),
+                _ => Line::default(),
+            });
+        }
+        row += 1;
+        skip = 0;
+    }
+
+    let gutter_area = Rect {
+        width: gutter as u16,
+        ..inner
+    };
+    let text_area = Rect {
+        x: inner.x + gutter as u16,
+        width: viewport.width as u16,
+        ..inner
+    };
+    Paragraph::new(Text::from(numbers))
+        .style(editor.style().fg(Color::DarkGray))
+        .right_aligned()
+        .render(gutter_area, buf);
+    let col = viewport.col.min(u16::MAX as usize) as u16;
+    Paragraph::new(Text::from(lines))
+        .style(editor.style())
+        .scroll((0, col))
+        .render(text_area, buf);
+    cursor
+        .filter(|&(x, _)| x < text_area.width as usize)
+        .map(|(x, y)| Position::new(text_area.x + x as u16, text_area.y + y as u16))
+}
+
+/// Number of cells taken by the character `c` drawn at the display column `cell`, where a tab reaches the next stop
+pub fn char_width(c: char, cell: usize, tab_len: usize) -> usize {
+    match c {
+        '\t' => tab_len - cell % tab_len,
+        c => c.width().unwrap_or(0),
+    }
+}
+
+/// Number of cells taken by the characters at the start of a display line
+pub fn display_width(chars: impl Iterator<Item = char>, tab_len: usize) -> usize {
+    chars.fold(0, |width, c| width + char_width(c, width, tab_len))
+}
+
+/// Characters of a line covered by the display columns from `left` to `right` inclusive, as a range of character
+/// indices. A tab or wide character is in the range when any of its cells is. Empty at the end of the line when the
+/// line ends before `left`.
+pub fn block_range(line: &str, (left, right): (usize, usize), tab_len: usize) -> (usize, usize) {
+    let mut cell = 0;
+    let mut start = None;
+    let mut end = 0;
+    for (i, c) in line.chars().enumerate() {
+        if cell > right {
+            break;
+        }
+        cell += char_width(c, cell, tab_len);
+        if start.is_none() && cell > left {
+            start = Some(i);
+        }
+        end = i + 1;
+    }
+    (start.unwrap_or(end), end)
+}
+
+/// Style of each character of a line and of the cell after its end, highlighted with the same priorities as
+/// tui-textarea: cursor, then search matches, then selection. The rest of the line has the style of its markup.
+fn char_styles(
+    editor: &TextArea,
+    row: usize,
+    selection: &Option<Selection>,
+    select_style: Style,
+    markup: Option<&[Style]>,
+) -> Vec<Option<Style>> {
+    let line = &editor.lines()[row];
+    let len = line.chars().count();
+    let (cursor_row, cursor_col) = editor.cursor();
+
+    let mut styles: Vec<Option<Style>> = vec![None; len + 1];
+    match *selection {
+        Some(Selection::Chars((start_row, start_col), (end_row, end_col)))
+            if (start_row..=end_row).contains(&row) =>
+        {
+            let from = if row == start_row { start_col } else { 0 };
+            let to = if row == end_row { end_col } else { len + 1 }; // Show the selected line break
+            for style in styles.iter_mut().take(to).skip(from) {
+                *style = Some(select_style);
+            }
+        }
+        Some(Selection::Block((top, left), (bottom, right))) if (top..=bottom).contains(&row) => {
+            let tab_len = (editor.tab_length() as usize).max(1);
+            let (start, end) = block_range(line, (left, right), tab_len);
+            for style in &mut styles[start..end] {
+                *style = Some(select_style);
+            }
+        }
+        _ => (),
+    }
+    if let Some(pattern) = editor.search_pattern() {
+        for found in pattern.find_iter(line).filter(|found| !found.is_empty()) {
+            let start = line[..found.start()].chars().count();
+            let end = start + found.as_str().chars().count();
+            for style in &mut styles[start..end] {
+                *style = Some(editor.search_style());
+            }
+        }
+    }
+    let base = match row == cursor_row {
+        true => {
+            styles[cursor_col.min(len)] = Some(editor.cursor_style());
+            editor.cursor_line_style()
+        }
+        false => Style::default(),
+    };
+    for (col, style) in styles[..len].iter_mut().enumerate() {
+        if style.is_none() {
+            *style = Some(match markup.and_then(|markup| markup.get(col)) {
+                Some(markup) => base.patch(*markup),
+                None => base,
+            });
+        }
+    }
+    styles
+}
+
+/// Spans of the characters from `start` to `end` of a line, with the cell after the end of the line on its last
+/// display line
+fn segment_line<'a>(
+    editor: &TextArea,
+    row: usize,
+    (start, end): (usize, usize),
+    last: bool,
+    styles: &[Option<Style>],
+    tab_len: usize,
+) -> Line<'a> {
+    let chars = editor.lines()[row].chars().skip(start).take(end - start);
+    let mut spans = Vec::new();
+    let mut run = String::new();
+    let mut run_style = None;
+    let mut width = 0;
+    for (c, style) in chars.zip(&styles[start..end]) {
+        if *style != run_style && !run.is_empty() {
+            spans.push(Span::styled(
+                std::mem::take(&mut run),
+                run_style.unwrap_or_default(),
+            ));
+        }
+        run_style = *style;
+        let len = char_width(c, width, tab_len);
+        match c {
+            '\t' => run.push_str(&" ".repeat(len)),
+            c => run.push(c),
+        }
+        width += len;
+    }
+    if !run.is_empty() {
+        spans.push(Span::styled(run, run_style.unwrap_or_default()));
+    }
+    if let Some(style) = styles[end].filter(|_| last) {
+        spans.push(Span::styled(" ", style));
+    }
+    Line::from(spans)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wraps_after_spaces() {
+        assert_eq!(wrap_line("the quick brown", 10, 4), [(0, 10), (10, 15)]);
+        assert_eq!(wrap_line("", 10, 4), [(0, 0)]);
+        assert_eq!(wrap_line("abc", 0, 4), [(0, 3)]);
+    }
+
+    #[test]
+    fn splits_words_longer_than_the_width() {
+        assert_eq!(wrap_line("abcdefghij", 4, 4), [(0, 4), (4, 8), (8, 10)]);
+    }
+
+    #[test]
+    fn full_line_gets_a_display_line_for_the_cursor() {
+        assert_eq!(wrap_line("abcd", 4, 4), [(0, 4), (4, 4)]);
+    }
+
+    #[test]
+    fn measures_tabs_and_wide_characters_in_cells() {
+        assert_eq!(wrap_line("\tab", 6, 4), [(0, 3), (3, 3)]);
+        assert_eq!(wrap_line("a\tb", 4, 4), [(0, 2), (2, 3)]);
+        assert_eq!(wrap_line("日本語", 4, 4), [(0, 2), (2, 3)]);
+        assert_eq!(wrap_line("日本語", 1, 4), [(0, 1), (1, 2), (2, 3), (3, 3)]);
+    }
+
+    #[test]
+    fn block_ranges_cover_whole_characters() {
+        assert_eq!(block_range("日本語", (1, 2), 4), (0, 2));
+        assert_eq!(block_range("\tab", (2, 4), 4), (0, 2));
+        assert_eq!(block_range("ab", (3, 5), 4), (2, 2));
+    }
+
+    #[test]
+    fn segment_of_column() {
+        let segments = wrap_line("the quick brown", 10, 4);
+        assert_eq!(segment_of(&segments, 9), 0);
+        assert_eq!(segment_of(&segments, 10), 1);
+        assert_eq!(segment_of(&segments, 15), 1);
+    }
+}
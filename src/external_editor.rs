@@ -0,0 +1,62 @@
+// Writing a message in the user's own editor ($VISUAL or $EDITOR), the terminal being handed over while it runs
+
+use std::{
+    env,
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::Path,
+};
+
+use crossterm::{
+    event::{DisableBracketedPaste, EnableBracketedPaste},
+    execute,
+    terminal::{EnterAlternateScreen, enable_raw_mode},
+};
+use ratatui::DefaultTerminal;
+use tokio::process::Command;
+
+/// Open `text` in the external editor and return the text it was saved with
+pub async fn edit(terminal: &mut DefaultTerminal, text: &str) -> io::Result<String> {
+    let path = env::temp_dir().join(format!("halfmoon-{}.md", std::process::id()));
+    create(&path, text)?;
+
+    // Editors not asking for bracketed paste would get the markers around pasted text as keys
+    let _ = execute!(io::stdout(), DisableBracketedPaste);
+    ratatui::restore();
+    let status = command(&path).status().await;
+    enable_raw_mode()?;
+    execute!(io::stdout(), EnterAlternateScreen, EnableBracketedPaste)?;
+    terminal.clear()?; // Redraw everything the editor drew over
+
+    let text = match status {
+        Ok(status) if status.success() => fs::read_to_string(&path),
+        Ok(status) => Err(io::Error::other(format!("editor exited with {}", status))),
+        Err(e) => Err(e),
+    };
+    let _ = fs::remove_file(&path);
+    text
+}
+
+/// Write `text` to a new file only the user can read. A file already at `path` is not reused, as it may be a link
+/// planted by someone else in the shared temporary directory.
+fn create(path: &Path, text: &str) -> io::Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(path)?;
+    file.write_all(text.as_bytes()).inspect_err(|_| {
+        let _ = fs::remove_file(path);
+    })
+}
+
+/// Command running the editor on `path`. The variables may hold arguments, as in "code --wait"
+fn command(path: &Path) -> Command {
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+    let mut words = editor.split_whitespace();
+    let mut command = Command::new(words.next().unwrap_or("vi"));
+    command.args(words).arg(path);
+    command
+}
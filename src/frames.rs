@@ -0,0 +1,105 @@
+// Pacing of the redraws. Keys are answered with a frame at once, while the updates of a streamed reply only mark the
+// screen as changed, drawn at most `max_fps` times per second. Nothing is drawn when nothing changed.
+
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+pub struct Frames {
+    interval: Duration,
+    last: Option<Instant>, // When the last frame was drawn
+    changed: bool,         // The screen changed since the last frame
+    urgent: bool,          // The change answers the user, drawn without waiting
+}
+
+impl Frames {
+    pub fn new(max_fps: u32) -> Self {
+        Self {
+            interval: Duration::from_secs(1) / max_fps.max(1),
+            last: None,
+            changed: true,
+            urgent: true,
+        }
+    }
+
+    /// Draw the change before waiting for the next event
+    pub fn now(&mut self) {
+        self.changed = true;
+        self.urgent = true;
+    }
+
+    /// Draw the change with the next frame
+    pub fn later(&mut self) {
+        self.changed = true;
+    }
+
+    pub fn due(&self) -> bool {
+        self.changed
+            && (self.urgent || self.last.is_none_or(|last| last.elapsed() >= self.interval))
+    }
+
+    pub fn drawn(&mut self) {
+        self.last = Some(Instant::now());
+        self.changed = false;
+        self.urgent = false;
+    }
+
+    /// When the next frame is to be drawn, if there is a change to draw
+    pub fn deadline(&self) -> Option<Instant> {
+        match self.changed {
+            true => Some(
+                self.last
+                    .map_or_else(Instant::now, |last| last + self.interval),
+            ),
+            false => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn draws_the_first_frame_at_once() {
+        let frames = Frames::new(30);
+        assert!(frames.due());
+        assert!(frames.deadline().is_some());
+    }
+
+    #[test]
+    fn draws_nothing_when_nothing_changed() {
+        let mut frames = Frames::new(30);
+        frames.drawn();
+        assert!(!frames.due());
+        assert_eq!(frames.deadline(), None);
+    }
+
+    #[test]
+    fn waits_for_the_next_frame_for_a_later_change() {
+        let mut frames = Frames::new(1);
+        frames.drawn();
+        frames.later();
+        assert!(!frames.due());
+        assert_eq!(
+            frames.deadline(),
+            frames.last.map(|last| last + Duration::from_secs(1))
+        );
+        frames.now();
+        assert!(frames.due());
+    }
+
+    #[test]
+    fn draws_a_later_change_once_the_interval_passed() {
+        let mut frames = Frames::new(1000);
+        frames.drawn();
+        std::thread::sleep(Duration::from_millis(2));
+        frames.later();
+        assert!(frames.due());
+    }
+
+    #[test]
+    fn draws_at_least_once_per_second() {
+        assert_eq!(Frames::new(0).interval, Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,90 @@
+// Messages sent to each persona, kept in $XDG_DATA_HOME/halfmoon/history/ to be recalled in the input editor.
+// Each line of a file is a message, with its line breaks and backslashes escaped.
+
+use std::{fs, path::PathBuf};
+
+use crate::config;
+
+const MAX_ENTRIES: usize = 1000;
+
+pub struct InputHistory {
+    path: Option<PathBuf>,
+    entries: Vec<String>,
+}
+
+impl InputHistory {
+    pub fn load(persona: &str) -> Self {
+        let path = config::persona_file("history", persona);
+        let entries = match path.as_ref().and_then(|path| fs::read_to_string(path).ok()) {
+            Some(text) => text.lines().map(unescape).collect(),
+            None => Vec::new(),
+        };
+        Self { path, entries }
+    }
+
+    pub fn entries(&self) -> &[String] {
+        &self.entries
+    }
+
+    pub fn push(&mut self, text: &str) {
+        let text = text.trim_end_matches('\n');
+        if text.trim().is_empty() || self.entries.last().is_some_and(|last| last == text) {
+            return;
+        }
+        self.entries.push(text.to_string());
+        if self.entries.len() > MAX_ENTRIES {
+            self.entries.remove(0);
+        }
+        self.save();
+    }
+
+    fn save(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        if let Some(dir) = path.parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        let lines: Vec<String> = self.entries.iter().map(|entry| escape(entry)).collect();
+        let _ = fs::write(path, lines.join("\n"));
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+fn unescape(line: &str) -> String {
+    let mut text = String::new();
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('n') => text.push('\n'),
+                Some(c) => text.push(c),
+                None => text.push('\\'),
+            },
+            c => text.push(c),
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escaped_messages_fit_on_a_line() {
+        let text = "first line\nC:\\path\\n\n";
+        let line = escape(text);
+        assert!(!line.contains('\n'));
+        assert_eq!(unescape(&line), text);
+    }
+
+    #[test]
+    fn unescape_keeps_stray_backslashes() {
+        assert_eq!(unescape("a\\b\\"), "ab\\");
+        assert_eq!(unescape("\\\\n"), "\\n");
+    }
+}
@@ -0,0 +1,46 @@
+// Placeholder macros of the messages, replaced by the names of the char and the user when libmoon sends them.
+// libmoon takes the user persona as an argument of `Persona::system_prompt` but has no accessor giving it to the
+// interface, so the preview has no user name until libmoon exposes one, and says so when {{user}} is left.
+
+use std::sync::LazyLock;
+
+use regex::{Captures, Regex};
+
+pub const MACROS: [&str; 2] = ["{{char}}", "{{user}}"];
+
+static MACRO: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)\{\{(char|user)\}\}").expect("valid macro pattern"));
+
+/// Text as it will be sent to the persona named `char` by the one named `user`, {{user}} being left as it is
+/// when the user is not known
+pub fn expand(text: &str, char: &str, user: Option<&str>) -> String {
+    MACRO
+        .replace_all(text, |captures: &Captures| {
+            match captures[1].to_lowercase().as_str() {
+                "char" => char.to_string(),
+                _ => user.unwrap_or(&captures[0]).to_string(),
+            }
+        })
+        .into_owned()
+}
+
+/// Whether `text` still has a {{user}} macro
+pub fn has_user(text: &str) -> bool {
+    MACRO
+        .captures_iter(text)
+        .any(|captures| captures[1].eq_ignore_ascii_case("user"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expands_the_known_names() {
+        let text = "{{char}} waves at {{User}}";
+        assert_eq!(expand(text, "Ann", Some("Bob")), "Ann waves at Bob");
+        assert_eq!(expand(text, "Ann", None), "Ann waves at {{User}}");
+        assert!(has_user(&expand(text, "Ann", None)));
+        assert!(!has_user(&expand(text, "Ann", Some("Bob"))));
+    }
+}
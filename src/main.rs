@@ -1,40 +1,82 @@
-use std::io;
+use std::{io, panic, time::Duration};
 
-use crossterm::event::EventStream;
+use crossterm::{
+    event::{DisableBracketedPaste, EnableBracketedPaste, EventStream},
+    execute,
+};
 use futures::StreamExt;
-use libmoon::{moon::Moon, persona::Persona};
+use libmoon::{
+    chat::ChatUpdate,
+    moon::{Moon, MoonUpdate},
+    persona::Persona,
+};
 use ratatui::{DefaultTerminal, Frame, crossterm::event::Event};
-use tokio::select;
+use tokio::{
+    select,
+    time::{self, MissedTickBehavior},
+};
 
 use crate::{
+    activity::SPINNER_PERIOD,
     chat_widget::{ChatState, ChatWidget},
+    frames::Frames,
     selector_widget::{SelectorState, SelectorWidget},
 };
 
+mod activity;
 mod chat_widget;
+mod config;
+mod drafts;
 mod editor_widget;
+mod external_editor;
+mod frames;
+mod input_history;
+mod macros;
+mod registers;
 mod selector_widget;
 
 #[tokio::main]
 async fn main() -> io::Result<()> {
     let terminal = ratatui::init();
+    // Pasted text comes as one event instead of keys, which could run commands
+    disable_paste_on_panic();
+    if let Err(e) = execute!(io::stdout(), EnableBracketedPaste) {
+        ratatui::restore();
+        return Err(e);
+    }
     let app_result = App::new().run(terminal).await;
+    let _ = execute!(io::stdout(), DisableBracketedPaste);
     ratatui::restore();
     app_result
 }
 
+/// Leave bracketed paste before the hook set by ratatui restores the terminal on a panic
+fn disable_paste_on_panic() {
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        let _ = execute!(io::stdout(), DisableBracketedPaste);
+        hook(info);
+    }));
+}
+
 enum AppCommand {
     ToggleSelection,
     CharSelection(Persona),
+    ExternalEditor(String),
     None,
+    Ignored, // The event changed nothing, there is no frame to draw
     Quit,
 }
 
+/// Time between two writes of the drafts, which are also written on exit
+const AUTOSAVE_PERIOD: Duration = Duration::from_secs(5);
+
 struct App {
     moon: Moon,
     chat_state: ChatState,
     selector_state: Option<SelectorState>,
     event_stream: EventStream,
+    external_edit: Option<String>, // Text to open in the external editor before the next frame
     exit: bool,
 }
 
@@ -45,31 +87,71 @@ impl App {
             moon.chat.title(),
             moon.chat.get_history(),
             moon.chat.get_history_structure(),
+            moon.gateway.chars.clone(),
         );
         Self {
             moon,
             chat_state,
             selector_state: None,
             event_stream: EventStream::new(),
+            external_edit: None,
             exit: false,
         }
     }
 
     async fn run(mut self, mut terminal: DefaultTerminal) -> io::Result<()> {
+        let mut autosave = time::interval(AUTOSAVE_PERIOD);
+        let mut frames = Frames::new(config::get().max_fps);
+        let mut spinner = time::interval(SPINNER_PERIOD);
+        spinner.set_missed_tick_behavior(MissedTickBehavior::Skip);
         loop {
-            terminal.draw(|frame| self.draw(frame))?;
+            if frames.due() {
+                self.chat_state.refresh(&self.moon.chat);
+                terminal.draw(|frame| self.draw(frame))?;
+                frames.drawn();
+            }
+            let deadline = frames.deadline();
+            let next_frame = time::sleep_until(deadline.unwrap_or_else(time::Instant::now));
             select! {
-                Some(event) = self.event_stream.next() => self.input(event?).await,
-                mu = self.moon.recv() => self.chat_state.update_status(mu, &self.moon.chat),
+                Some(event) = self.event_stream.next() => {
+                    if self.input(event?).await {
+                        frames.now();
+                    }
+                }
+                mu = self.moon.recv() => {
+                    // Tokens can come faster than frames are worth drawing
+                    match mu {
+                        MoonUpdate::CU(ChatUpdate::StreamUpdate) => frames.later(),
+                        _ => frames.now(),
+                    }
+                    self.chat_state.update_status(mu, &self.moon.chat);
+                }
+                _ = autosave.tick() => self.chat_state.autosave(),
+                _ = spinner.tick(), if self.chat_state.busy() => frames.later(),
+                _ = next_frame, if deadline.is_some() => (),
             };
 
+            if let Some(text) = self.external_edit.take() {
+                // The old stream would keep reading the keys meant for the editor, a new one only starts once polled
+                self.event_stream = EventStream::new();
+                let result = external_editor::edit(&mut terminal, &text).await;
+                self.chat_state
+                    .external_edit(result, &text, &mut self.moon.chat);
+                frames.now();
+            }
+
             if self.exit {
+                self.chat_state.autosave();
                 return Ok(());
             }
         }
     }
 
-    async fn input(&mut self, event: Event) {
+    /// Handle a terminal event, returning whether the screen changed
+    async fn input(&mut self, event: Event) -> bool {
+        if let Event::Mouse(_) | Event::FocusGained | Event::FocusLost = event {
+            return false;
+        }
         let command = match &mut self.selector_state {
             Some(selector_state) => selector_state.handle_input(event).await,
             None => self.chat_state.input(event, &mut self.moon.chat),
@@ -83,13 +165,17 @@ impl App {
                 }
             }
             AppCommand::CharSelection(persona) => {
+                self.chat_state.set_persona(persona.name());
                 self.moon.set_chars(persona);
                 self.chat_state.update_list(&self.moon.chat);
                 self.selector_state = None;
             }
+            AppCommand::ExternalEditor(text) => self.external_edit = Some(text),
             AppCommand::Quit => self.exit = true,
             AppCommand::None => (),
+            AppCommand::Ignored => return false,
         }
+        true
     }
 
     fn draw(&mut self, frame: &mut Frame) {
@@ -0,0 +1,231 @@
+// Vim-like registers shared by every editor and the chat view.
+// " is the unnamed register, a-z are named registers (A-Z append to them) and + and * are the system clipboard.
+// Macros are recorded in the registers as text, keys without a character being put in the private use area, and
+// replayed from the recorded keys until something else is stored in the register. Other texts are replayed as typed, so that the
+// characters of the private use area in them (as icon fonts use) stay characters.
+
+use std::{
+    collections::HashMap,
+    sync::{LazyLock, Mutex},
+};
+
+use arboard::Clipboard;
+use tui_textarea::{Input, Key};
+
+static REGISTERS: LazyLock<Mutex<Registers>> = LazyLock::new(Default::default);
+
+#[derive(Default)]
+struct Registers {
+    texts: HashMap<char, String>,
+    macros: HashMap<char, Vec<Input>>, // Keys of the recorded macros, replayed instead of their text
+    clipboard: Option<Clipboard>,      // Kept alive so that the copied text stays available on X11
+}
+
+impl Registers {
+    fn clipboard(&mut self) -> Result<&mut Clipboard, arboard::Error> {
+        if self.clipboard.is_none() {
+            self.clipboard = Some(Clipboard::new()?);
+        }
+        Ok(self.clipboard.as_mut().expect("clipboard was just created"))
+    }
+}
+
+pub fn is_valid(register: char) -> bool {
+    matches!(register, '"' | '+' | '*') || register.is_ascii_alphabetic()
+}
+
+pub fn get(register: char) -> Option<String> {
+    let mut registers = REGISTERS.lock().ok()?;
+    match register {
+        '+' | '*' => registers.clipboard().ok()?.get_text().ok(),
+        register => registers.texts.get(&register.to_ascii_lowercase()).cloned(),
+    }
+}
+
+/// Store text in a register, and in the unnamed register as Vim does
+pub fn set(register: char, text: String) -> Result<(), arboard::Error> {
+    let Ok(mut registers) = REGISTERS.lock() else {
+        return Ok(());
+    };
+    registers.texts.insert('"', text.clone());
+    registers.macros.remove(&register.to_ascii_lowercase()); // The text is no longer the recorded macro
+    match register {
+        '+' | '*' => registers.clipboard()?.set_text(text),
+        '"' => Ok(()),
+        register if register.is_ascii_uppercase() => {
+            let register = register.to_ascii_lowercase();
+            registers.texts.entry(register).or_default().push_str(&text);
+            Ok(())
+        }
+        register => {
+            registers.texts.insert(register, text);
+            Ok(())
+        }
+    }
+}
+
+/// Keys of the macro in a register, which can be any text
+pub fn get_macro(register: char) -> Option<Vec<Input>> {
+    let recorded = REGISTERS
+        .lock()
+        .ok()?
+        .macros
+        .get(&register.to_ascii_lowercase())
+        .cloned();
+    match recorded {
+        Some(keys) => Some(keys),
+        None => get(register).map(|text| text_keys(&text)),
+    }
+}
+
+/// Record a macro in a named register. Unlike a yank, it does not go to the unnamed register.
+pub fn set_macro(register: char, keys: &[Input]) {
+    if let Ok(mut registers) = REGISTERS.lock() {
+        registers.texts.insert(register, keys_text(keys));
+        registers.macros.insert(register, keys.to_vec());
+    }
+}
+
+const MODIFIERS: u32 = 0xE000; // Followed by the Ctrl (1), Alt (2) and Shift (4) bits, before the modified key
+const SPECIAL_KEYS: u32 = 0xE010;
+const FUNCTION_KEYS: u32 = 0xE100;
+
+/// Keys without a character of their own, in the order of their code after SPECIAL_KEYS
+const SPECIAL: [Key; 8] = [
+    Key::Up,
+    Key::Down,
+    Key::Left,
+    Key::Right,
+    Key::Home,
+    Key::End,
+    Key::PageUp,
+    Key::PageDown,
+];
+
+/// Text of a macro, typed keys being their character and Ctrl-letters control characters, as in Vim
+fn keys_text(keys: &[Input]) -> String {
+    let mut text = String::new();
+    for input in keys {
+        let c = match input.key {
+            Key::Char(c) => c,
+            Key::Enter => '\n',
+            Key::Tab => '\t',
+            Key::Backspace => '\x08',
+            Key::Esc => '\x1b',
+            Key::Delete => '\x7f',
+            Key::F(n) => special(FUNCTION_KEYS + n as u32),
+            key => match SPECIAL.iter().position(|&special_key| special_key == key) {
+                Some(i) => special(SPECIAL_KEYS + i as u32),
+                None => continue, // Mouse and termwiz keys are not replayed
+            },
+        };
+        let control = match input.key {
+            // Ctrl-H, Ctrl-I, Ctrl-J and Ctrl-M would be read back as Backspace, Tab and Enter
+            Key::Char(c)
+                if input.ctrl && !input.alt && c.is_ascii_lowercase() && !"hijm".contains(c) =>
+            {
+                Some((c as u8 - b'a' + 1) as char)
+            }
+            _ => None,
+        };
+        let shift = input.shift && !matches!(input.key, Key::Char(_)); // Shifted characters are their own
+        let modifiers = input.ctrl as u32 | (input.alt as u32) << 1 | (shift as u32) << 2;
+        match control {
+            Some(control) => text.push(control),
+            None if modifiers != 0 => {
+                text.push(special(MODIFIERS + modifiers));
+                text.push(c);
+            }
+            None => text.push(c),
+        }
+    }
+    text
+}
+
+/// Keys typing the text of a register, control characters being Ctrl-letters as in Vim
+fn text_keys(text: &str) -> Vec<Input> {
+    text.chars()
+        .map(|c| Input {
+            key: match c {
+                '\n' | '\r' => Key::Enter,
+                '\t' => Key::Tab,
+                '\x08' => Key::Backspace,
+                '\x1b' => Key::Esc,
+                '\x7f' => Key::Delete,
+                '\x01'..='\x1a' => Key::Char((b'a' + c as u8 - 1) as char),
+                c => Key::Char(c),
+            },
+            ctrl: matches!(c, '\x01'..='\x1a') && !"\x08\t\n\r".contains(c),
+            alt: false,
+            shift: c.is_uppercase(),
+        })
+        .collect()
+}
+
+fn special(code: u32) -> char {
+    char::from_u32(code).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn input(key: Key, ctrl: bool, alt: bool) -> Input {
+        Input {
+            key,
+            ctrl,
+            alt,
+            shift: false,
+        }
+    }
+
+    #[test]
+    fn recorded_macros_replay_their_keys() {
+        let keys = vec![
+            input(Key::Char('i'), false, false),
+            input(Key::Char('h'), false, false),
+            input(Key::Esc, false, false),
+            input(Key::Char('r'), true, false),
+            input(Key::Char('h'), true, false),
+            input(Key::Char('b'), false, true),
+            input(Key::Up, false, false),
+            input(Key::Left, true, false),
+            input(Key::F(5), false, false),
+            input(Key::Enter, false, false),
+            Input {
+                key: Key::Char('A'),
+                ctrl: false,
+                alt: false,
+                shift: true,
+            },
+        ];
+        set_macro('k', &keys);
+        assert_eq!(get_macro('k'), Some(keys));
+    }
+
+    #[test]
+    fn private_use_characters_are_typed() {
+        // A character of an icon font, in the range the text of a recorded macro puts special keys in
+        let icon = special(SPECIAL_KEYS);
+        let _ = set('i', format!("a{}", icon));
+        let keys = get_macro('i').unwrap_or_default();
+        assert_eq!(keys[1], input(Key::Char(icon), false, false));
+
+        set_macro('j', &[input(Key::Char(icon), false, false)]);
+        assert_eq!(
+            get_macro('j'),
+            Some(vec![input(Key::Char(icon), false, false)])
+        );
+    }
+
+    #[test]
+    fn macros_are_register_texts() {
+        set_macro('m', &text_keys("ihi\x1b"));
+        assert_eq!(get('m').as_deref(), Some("ihi\x1b"));
+        assert_ne!(get('"').as_deref(), Some("ihi\x1b"));
+
+        let _ = set('n', "dd".to_string());
+        let keys = get_macro('n').unwrap_or_default();
+        assert_eq!(keys, vec![input(Key::Char('d'), false, false); 2]);
+    }
+}